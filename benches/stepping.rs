@@ -0,0 +1,48 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use multibaker::{State, Transition};
+use std::hint::black_box;
+
+// Compare stepping into fresh dice one step at a time against fast_forward
+fn forward_exploration(c: &mut Criterion) {
+    let transition = Transition::random_step();
+    let mut group = c.benchmark_group("forward 10000 fresh steps");
+    group.bench_function("step_forward_n", |b| {
+        b.iter(|| {
+            let mut state = State::from_seed(0, 42);
+            state.step_forward_n(&transition, black_box(10_000));
+            *state.macrostate()
+        })
+    });
+    group.bench_function("fast_forward", |b| {
+        b.iter(|| {
+            let mut state = State::from_seed(0, 42);
+            state.fast_forward(&transition, black_box(10_000));
+            *state.macrostate()
+        })
+    });
+    group.finish();
+}
+
+// Compare reseeding a fresh RNG at every step against advancing one cached stream
+fn die_sources(c: &mut Criterion) {
+    let transition = Transition::random_step();
+    let mut group = c.benchmark_group("forward 10000 steps by die source");
+    group.bench_function("uniform_rolls", |b| {
+        b.iter(|| {
+            let mut state = State::with_die_source(0, State::uniform_rolls(42));
+            state.fast_forward(&transition, black_box(10_000));
+            *state.macrostate()
+        })
+    });
+    group.bench_function("sequential_rolls", |b| {
+        b.iter(|| {
+            let mut state = State::with_die_source(0, State::sequential_rolls(42));
+            state.fast_forward(&transition, black_box(10_000));
+            *state.macrostate()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, forward_exploration, die_sources);
+criterion_main!(benches);
@@ -0,0 +1,61 @@
+use crate::{State, UniformDie};
+use rand::Rng;
+
+// Collects the options for a State before building it
+// The defaults match State::new: macrostate zero, a random seed, and faces 0..6
+pub struct StateBuilder<M = i32> {
+    macrostate: M,
+    seed: Option<u64>,
+    die_range: (i32, i32),
+    future_dice: Vec<i32>,
+}
+
+// Only the default macrostate type gets Default, so StateBuilder::default() needs no annotation
+impl Default for StateBuilder {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl<M: Clone> StateBuilder<M> {
+    // Start from the given macrostate, with the other options at their defaults
+    pub fn new(macrostate: M) -> Self {
+        StateBuilder {
+            macrostate,
+            seed: None,
+            die_range: (0, 6),
+            future_dice: Vec::new(),
+        }
+    }
+
+    // The macrostate at t=0
+    pub fn macrostate(mut self, macrostate: M) -> Self {
+        self.macrostate = macrostate;
+        self
+    }
+
+    // The seed from which the dice are rolled
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    // Roll a die with faces low..high; build panics if the range is empty
+    pub fn die_range(mut self, low: i32, high: i32) -> Self {
+        self.die_range = (low, high);
+        self
+    }
+
+    // Dice to replay in order before rolling any
+    pub fn preload_future(mut self, future_dice: Vec<i32>) -> Self {
+        self.future_dice = future_dice;
+        self
+    }
+
+    pub fn build(self) -> State<M> {
+        let seed = self.seed.unwrap_or_else(|| rand::rng().random());
+        let (low, high) = self.die_range;
+        State::from_uniform_die(self.macrostate, UniformDie::with_range(seed, low, high))
+            .replaying(self.future_dice)
+    }
+}
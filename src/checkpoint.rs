@@ -0,0 +1,61 @@
+use crate::State;
+
+// A snapshot of a state taken by State::checkpoint, to return to with State::restore
+// It keeps the whole state, dice caches and die included, so restoring is exact
+#[derive(Clone)]
+pub struct Checkpoint<M = i32> {
+    pub(crate) state: State<M>,
+}
+
+impl<M: Clone> Checkpoint<M> {
+    // The time step of the snapshot
+    pub fn t(&self) -> i32 {
+        self.state.t()
+    }
+
+    // The macrostate of the snapshot
+    pub fn macrostate(&self) -> &M {
+        self.state.macrostate()
+    }
+
+    // The cached past dice of the snapshot, most recent last
+    pub fn past_dice(&self) -> &[i32] {
+        self.state.past_dice()
+    }
+
+    // The cached future dice of the snapshot, next die last
+    pub fn future_dice(&self) -> &[i32] {
+        self.state.future_dice()
+    }
+
+    // The seed of the snapshot
+    pub fn microstate_seed(&self) -> u64 {
+        self.state.microstate_seed()
+    }
+}
+
+// What changed from one checkpoint to a later one, as reported by Checkpoint::diff
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CheckpointDiff {
+    pub delta_t: i32,
+    // The change in macrostate, wrapping around like random_step
+    pub delta_macrostate: i32,
+    // The changes in the number of cached past and future dice. A forward step over a cached
+    // die moves it from the future cache to the past one, while a fresh roll only adds to the
+    // past, and the other way round stepping backward
+    pub delta_past_dice: i32,
+    pub delta_future_dice: i32,
+}
+
+impl Checkpoint {
+    // What changed from a to b
+    pub fn diff(a: &Checkpoint, b: &Checkpoint) -> CheckpointDiff {
+        let len = |dice: &[i32]| dice.len() as i32;
+        CheckpointDiff {
+            delta_t: b.t() - a.t(),
+            delta_macrostate: b.macrostate().wrapping_sub(*a.macrostate()),
+            delta_past_dice: len(b.past_dice()) - len(a.past_dice()),
+            delta_future_dice: len(b.future_dice()) - len(a.future_dice()),
+        }
+    }
+}
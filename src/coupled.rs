@@ -0,0 +1,55 @@
+use crate::{State, Transition};
+
+// Steps a primary state with its own transition while a secondary state tracks it live,
+// adding coupling(primary macrostate, secondary die) to the secondary at each step, wrapping
+// around like random_step
+pub struct CoupledTransition {
+    primary: Transition,
+    coupling: Box<dyn Fn(i32, i32) -> i32 + Send + Sync>,
+}
+
+impl CoupledTransition {
+    pub fn new(
+        primary: Transition,
+        coupling: impl Fn(i32, i32) -> i32 + Send + Sync + 'static,
+    ) -> Self {
+        CoupledTransition {
+            primary,
+            coupling: Box::new(coupling),
+        }
+    }
+
+    // The secondary reads the primary's macrostate before the primary steps
+    pub fn step_forward(&self, primary: &mut State, secondary: &mut State) {
+        let observed = *primary.macrostate();
+        secondary.apply_forward(|&macrostate, die| {
+            macrostate.wrapping_add((self.coupling)(observed, die))
+        });
+        primary.step_forward(&self.primary);
+    }
+
+    // The primary steps back first, so the secondary undoes the same value it added
+    pub fn step_backward(&self, primary: &mut State, secondary: &mut State) {
+        primary.step_backward(&self.primary);
+        let observed = *primary.macrostate();
+        secondary.apply_backward(|&macrostate, die| {
+            macrostate.wrapping_sub((self.coupling)(observed, die))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coupling_wraps_and_rewinds() {
+        let coupled = CoupledTransition::new(Transition::idle(), |observed, _| observed);
+        let mut primary = State::from_seed(i32::MAX, 1);
+        let mut secondary = State::from_seed(i32::MAX, 2);
+        coupled.step_forward(&mut primary, &mut secondary);
+        assert_eq!(*secondary.macrostate(), -2);
+        coupled.step_backward(&mut primary, &mut secondary);
+        assert_eq!(*secondary.macrostate(), i32::MAX);
+    }
+}
@@ -0,0 +1,194 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::sync::Mutex;
+
+// Rolls the die for a given time step; the roll must depend only on t
+// The die for the step from t to t + 1 is rolled at t, and every u64 is a valid input:
+// negative time steps sign-extend, so t = -1 rolls at u64::MAX, t = -2 at u64::MAX - 1, etc.
+pub trait DieSource: Send + Sync {
+    fn roll(&self, t: u64) -> i32;
+}
+
+impl<F: Fn(u64) -> i32 + Send + Sync> DieSource for F {
+    fn roll(&self, t: u64) -> i32 {
+        self(t)
+    }
+}
+
+// A die that rolls uniformly in low..high, independently at each time step
+pub struct UniformDie {
+    pub low: i32,
+    pub high: i32,
+    pub seed: u64,
+}
+
+impl UniformDie {
+    // A six-sided die with faces 0..6
+    pub fn new(seed: u64) -> Self {
+        Self::with_range(seed, 0, 6)
+    }
+
+    // A die with faces low..high; panics if the range is empty
+    pub fn with_range(seed: u64, low: i32, high: i32) -> Self {
+        assert!(low < high, "die range {}..{} is empty", low, high);
+        UniformDie { low, high, seed }
+    }
+}
+
+impl DieSource for UniformDie {
+    fn roll(&self, t: u64) -> i32 {
+        ChaCha8Rng::seed_from_u64(t ^ self.seed).random_range(self.low..self.high)
+    }
+}
+
+// A six-sided die keyed by a full 256-bit ChaCha key rather than a u64 seed
+// Each time step reads from its own ChaCha stream, so the rolls at different t are independent
+pub struct KeyedDie {
+    pub key: [u8; 32],
+}
+
+impl DieSource for KeyedDie {
+    fn roll(&self, t: u64) -> i32 {
+        let mut rng = ChaCha8Rng::from_seed(self.key);
+        rng.set_stream(t);
+        rng.random_range(0..6)
+    }
+}
+
+// A die with faces 0..weights.len(), loaded in proportion to the given weights
+// Rolls take constant time using Vose's alias method: each face owns a column holding its own
+// share of probability and one other face that tops the column up to 1 / n
+pub struct WeightedDie {
+    pub seed: u64,
+    // The chance of keeping each column's own face rather than its alias
+    prob: Vec<f64>,
+    alias: Vec<i32>,
+}
+
+impl WeightedDie {
+    // Panics unless the weights are finite, non-negative, and not all zero
+    pub fn new(seed: u64, weights: &[f64]) -> Self {
+        assert!(
+            weights.iter().all(|w| w.is_finite() && *w >= 0.0),
+            "die weights must be finite and non-negative"
+        );
+        let total: f64 = weights.iter().sum();
+        assert!(total > 0.0, "die weights must not all be zero");
+
+        let n = weights.len();
+        // Scale so that a column holding exactly 1 / n of the probability has height 1
+        let mut height: Vec<f64> = weights.iter().map(|w| w * n as f64 / total).collect();
+        let mut alias: Vec<i32> = (0..n as i32).collect();
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) =
+            (0..n).partition(|&i| height[i] < 1.0);
+        while let (Some(&short), Some(&tall)) = (small.last(), large.last()) {
+            small.pop();
+            alias[short] = tall as i32;
+            height[tall] -= 1.0 - height[short];
+            if height[tall] < 1.0 {
+                large.pop();
+                small.push(tall);
+            }
+        }
+        // Whatever is left is full up to rounding error
+        for i in small.into_iter().chain(large) {
+            height[i] = 1.0;
+        }
+        WeightedDie {
+            seed,
+            prob: height,
+            alias,
+        }
+    }
+}
+
+impl DieSource for WeightedDie {
+    fn roll(&self, t: u64) -> i32 {
+        let mut rng = ChaCha8Rng::seed_from_u64(t ^ self.seed);
+        let column = rng.random_range(0..self.prob.len());
+        if rng.random::<f64>() < self.prob[column] {
+            column as i32
+        } else {
+            self.alias[column]
+        }
+    }
+}
+
+// The number of consecutive time steps that share one SequentialDie stream
+const SEQUENTIAL_BLOCK: u64 = 1024;
+
+// A six-sided die that reads consecutive time steps from one cached ChaCha stream instead of
+// reseeding at every step, which makes long forward-only runs much cheaper
+// The stream is reseeded every 1024 steps, so rolls still depend only on t, but reaching a t
+// other than the next one costs up to 1024 draws: rewinding past the dice cache or jumping
+// around in time is far slower than with UniformDie. The rolls differ from UniformDie's as well
+pub struct SequentialDie {
+    pub seed: u64,
+    // The block the stream was seeded for and how many rolls have been drawn from it
+    stream: Mutex<(u64, u64, ChaCha8Rng)>,
+}
+
+impl SequentialDie {
+    pub fn new(seed: u64) -> Self {
+        SequentialDie {
+            seed,
+            stream: Mutex::new((0, 0, ChaCha8Rng::seed_from_u64(seed))),
+        }
+    }
+}
+
+impl DieSource for SequentialDie {
+    fn roll(&self, t: u64) -> i32 {
+        let (block, offset) = (t / SEQUENTIAL_BLOCK, t % SEQUENTIAL_BLOCK);
+        let mut stream = self.stream.lock().unwrap();
+        let (seeded_block, drawn, rng) = &mut *stream;
+        if *seeded_block != block || *drawn > offset {
+            *rng = ChaCha8Rng::seed_from_u64(block ^ self.seed);
+            *seeded_block = block;
+            *drawn = 0;
+        }
+        while *drawn < offset {
+            rng.random_range(0..6);
+            *drawn += 1;
+        }
+        *drawn += 1;
+        rng.random_range(0..6)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weighted_die_rolls_in_proportion_to_its_weights() {
+        let die = WeightedDie::new(5, &[0.0, 1.0, 3.0, 0.0, 6.0]);
+        let mut counts = [0; 5];
+        for t in 0..100_000 {
+            counts[die.roll(t) as usize] += 1;
+        }
+        assert_eq!((counts[0], counts[3]), (0, 0));
+        for (face, expected) in [(1, 10_000), (2, 30_000), (4, 60_000)] {
+            let count: i32 = counts[face];
+            assert!(
+                (count - expected).abs() < 1_000,
+                "face {} rolled {} times",
+                face,
+                count
+            );
+        }
+    }
+
+    #[test]
+    fn sequential_die_rolls_depend_only_on_t() {
+        let times: Vec<u64> = (1000..1100).chain(u64::MAX - 50..=u64::MAX).collect();
+        let forward = SequentialDie::new(9);
+        let rolls: Vec<i32> = times.iter().map(|&t| forward.roll(t)).collect();
+        let reverse = SequentialDie::new(9);
+        let mut reversed: Vec<i32> = times.iter().rev().map(|&t| reverse.roll(t)).collect();
+        reversed.reverse();
+        assert_eq!(reversed, rolls);
+        assert_eq!(forward.roll(1023), rolls[23]);
+        assert_eq!(forward.roll(1024), rolls[24]);
+    }
+}
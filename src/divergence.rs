@@ -0,0 +1,139 @@
+#[cfg(feature = "collect")]
+use crate::ensemble::mix;
+use crate::{State, Transition};
+#[cfg(feature = "collect")]
+use rayon::prelude::*;
+
+// The absolute difference between the macrostates of two states, as a u32 since the macrostates
+// of wrapping walks can be further apart than an i32 holds
+pub fn divergence(a: &State, b: &State) -> u32 {
+    a.macrostate().abs_diff(*b.macrostate())
+}
+
+#[cfg(feature = "collect")]
+// Step both states forward together, recording their divergence after each step
+pub fn divergence_over_time(
+    a: &mut State,
+    b: &mut State,
+    transition: &Transition,
+    steps: i32,
+) -> Vec<u32> {
+    (0..steps)
+        .map(|_| {
+            a.step_forward(transition);
+            b.step_forward(transition);
+            divergence(a, b)
+        })
+        .collect()
+}
+
+// Step both states forward together until their macrostates first differ, returning a's time
+// step at that point. If they already differ, that's a's current time step and nothing is
+// stepped. None if they still agree after max_steps steps
+pub fn first_divergence(
+    a: &mut State,
+    b: &mut State,
+    transition: &Transition,
+    max_steps: i32,
+) -> Option<i32> {
+    for _ in 0..max_steps {
+        if a.macrostate() != b.macrostate() {
+            return Some(a.t());
+        }
+        a.step_forward(transition);
+        b.step_forward(transition);
+    }
+    (a.macrostate() != b.macrostate()).then(|| a.t())
+}
+
+#[cfg(feature = "collect")]
+// Sweep perturbations of base's microstate in parallel for a heatmap: row i holds the mean
+// divergence after each of the given number of steps over an ensemble of pairs. The kth pair
+// reseeds two copies of base, one with base's seed mixed with k as in run_ensemble_seeded and
+// the other with that seed XOR perturbations[i], so the perturbation's set bits are the seed
+// bits it flips and a perturbation of 0 never diverges. Reseeding drops both copies' future
+// caches. Since UniformDie rolls from t ^ seed, flipping low seed bits only swaps dice between
+// nearby steps: a perturbation of 1 swaps each even step's die with the next, so its divergence
+// returns to 0 after every other step. Base itself is left where it is. Panics if base has a
+// custom die source, which can't be reseeded
+pub fn divergence_grid(
+    base: &State,
+    transition: &Transition,
+    perturbations: &[u64],
+    ensemble: usize,
+    steps: i32,
+) -> Vec<Vec<f64>> {
+    perturbations
+        .par_iter()
+        .map(|&perturbation| {
+            let mut total = vec![0.0; steps.max(0) as usize];
+            for k in 0..ensemble {
+                let seed = base.microstate_seed() ^ mix(k as u64);
+                let mut original = base.clone();
+                let mut perturbed = base.clone();
+                original.reseed(seed);
+                perturbed.reseed(seed ^ perturbation);
+                let series = divergence_over_time(&mut original, &mut perturbed, transition, steps);
+                for (total, divergence) in total.iter_mut().zip(series) {
+                    *total += f64::from(divergence);
+                }
+            }
+            total.iter().map(|total| total / ensemble as f64).collect()
+        })
+        .collect()
+}
+
+#[cfg(feature = "collect")]
+// Step a copy of base and a perturbed copy forward together, recording both macrostates after
+// each step. Base itself is left where it is
+pub fn compare_perturbed<M: Clone + PartialEq>(
+    base: &State<M>,
+    transition: &Transition<M>,
+    steps: i32,
+) -> Vec<(M, M)> {
+    let mut original = base.clone();
+    let mut perturbed = State::peturbed(base);
+    (0..steps)
+        .map(|_| {
+            original.step_forward(transition);
+            perturbed.step_forward(transition);
+            (
+                original.macrostate().clone(),
+                perturbed.macrostate().clone(),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "collect")]
+    #[test]
+    fn divergence_grid_averages_over_the_ensemble() {
+        let base = State::from_seed(0, 3);
+        let transition = Transition::random_step();
+        let grid = divergence_grid(&base, &transition, &[0, 1, 1 << 40], 16, 20);
+        assert_eq!(grid.len(), 3);
+        assert!(grid[0].iter().all(|&mean| mean == 0.0));
+        for row in &grid[1..] {
+            assert_eq!(row.len(), 20);
+            assert!(row.iter().any(|&mean| mean > 0.0));
+            // A mean over 16 integer divergences is a multiple of 1 / 16
+            assert!(row.iter().all(|mean| (mean * 16.0).fract() == 0.0));
+        }
+        assert_eq!(
+            divergence_grid(&base, &transition, &[1], 16, 20)[0],
+            grid[1]
+        );
+    }
+
+    #[test]
+    fn divergence_spans_the_whole_i32_range() {
+        let (a, b) = (State::from_seed(i32::MAX, 1), State::from_seed(-1, 1));
+        assert_eq!(divergence(&a, &b), 1 << 31);
+        let (a, b) = (State::from_seed(i32::MAX, 1), State::from_seed(i32::MIN, 1));
+        assert_eq!(divergence(&a, &b), u32::MAX);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{State, Transition, Welford};
+use rayon::prelude::*;
+
+// Step perturbed copies of base forward in parallel, returning their final macrostates
+pub fn run_ensemble<M>(
+    base: &State<M>,
+    transition: &Transition<M>,
+    copies: usize,
+    steps: i32,
+) -> Vec<M>
+where
+    M: Clone + PartialEq + Send + Sync,
+{
+    (0..copies)
+        .into_par_iter()
+        .map(|_| {
+            let mut state = State::peturbed(base);
+            for _ in 0..steps {
+                state.step_forward(transition);
+            }
+            state.macrostate().clone()
+        })
+        .collect()
+}
+
+// Like run_ensemble, but seed each copy from base_seed and its index rather than at random,
+// so the result is reproducible however rayon schedules the copies
+pub fn run_ensemble_seeded<M>(
+    base_macrostate: M,
+    base_seed: u64,
+    transition: &Transition<M>,
+    copies: usize,
+    steps: i32,
+) -> Vec<M>
+where
+    M: Clone + PartialEq + Send + Sync,
+{
+    (0..copies)
+        .into_par_iter()
+        .map(|index| {
+            let seed = base_seed ^ mix(index as u64);
+            let mut state = State::from_seed(base_macrostate.clone(), seed);
+            for _ in 0..steps {
+                state.step_forward(transition);
+            }
+            state.macrostate().clone()
+        })
+        .collect()
+}
+
+// The macrostate at time t, which may be negative, of a fresh state from each seed, in
+// parallel. The results are in the order of the seeds, however rayon schedules them
+pub fn distribution_at<M>(
+    base_macrostate: M,
+    transition: &Transition<M>,
+    t: i32,
+    seeds: &[u64],
+) -> Vec<M>
+where
+    M: Clone + PartialEq + Send + Sync,
+{
+    seeds
+        .par_iter()
+        .map(|&seed| {
+            let mut state = State::from_seed(base_macrostate.clone(), seed);
+            state.goto(transition, t);
+            state.macrostate().clone()
+        })
+        .collect()
+}
+
+// The population variance of the macrostate across an ensemble seeded as in run_ensemble_seeded
+// from macrostate 0, after each of the given number of steps. For random_step with dice in 0..6
+// each step adds the die's variance of 35 / 12, so this should grow linearly from (1, 35 / 12)
+pub fn variance_growth(
+    seed: u64,
+    transition: &Transition,
+    steps: i32,
+    ensemble: usize,
+) -> Vec<(i32, f64)> {
+    let paths: Vec<Vec<i32>> = (0..ensemble)
+        .into_par_iter()
+        .map(|index| {
+            let mut state = State::from_seed(0, seed ^ mix(index as u64));
+            (0..steps)
+                .map(|_| {
+                    state.step_forward(transition);
+                    *state.macrostate()
+                })
+                .collect()
+        })
+        .collect();
+    (0..steps.max(0) as usize)
+        .map(|step| {
+            let mut welford = Welford::default();
+            for path in &paths {
+                welford.push(path[step] as f64);
+            }
+            (step as i32 + 1, welford.variance())
+        })
+        .collect()
+}
+
+// The splitmix64 finalizer, which spreads consecutive indices across all bits of the seed
+pub(crate) fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_ensembles_are_reproducible() {
+        // The checksum depends on every die, so members only agree if their dice do
+        let transition = Transition::checksum_step();
+        let ensemble = run_ensemble_seeded((0, 0), 42, &transition, 8, 50);
+        assert_eq!(
+            run_ensemble_seeded((0, 0), 42, &transition, 8, 50),
+            ensemble
+        );
+        let other = run_ensemble_seeded((0, 0), 43, &transition, 8, 50);
+        assert!(other.iter().all(|member| !ensemble.contains(member)));
+        let mut members = ensemble.clone();
+        members.sort();
+        members.dedup();
+        assert_eq!(members.len(), ensemble.len());
+    }
+}
@@ -0,0 +1,86 @@
+use std::error::Error;
+use std::fmt;
+
+// Why a fallible step could not be taken; the state is left unchanged
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepError {
+    // The evolved macrostate does not fit in its type
+    Overflow,
+    // The evolved macrostate would leave the range the transition is confined to
+    OutOfBounds,
+    // The condition being stepped towards still didn't hold after the allowed number of steps
+    MaxSteps,
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepError::Overflow => write!(f, "macrostate overflowed"),
+            StepError::OutOfBounds => write!(f, "macrostate left its bounds"),
+            StepError::MaxSteps => write!(f, "ran out of steps"),
+        }
+    }
+}
+
+impl Error for StepError {}
+
+// Why a transition spec such as "record:5" could not be parsed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseTransitionError {
+    // The spec as given
+    pub spec: String,
+    // What was wrong with it
+    pub reason: String,
+}
+
+impl fmt::Display for ParseTransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid transition {:?}: {}", self.spec, self.reason)
+    }
+}
+
+impl Error for ParseTransitionError {}
+
+// Why bytes could not be decoded by Trajectory::from_bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeTrajectoryError {
+    // There weren't enough bytes for the length prefix
+    MissingLength,
+    // The bytes after the prefix don't hold exactly the number of points it gives
+    WrongLength { points: u64, bytes: usize },
+}
+
+impl fmt::Display for DecodeTrajectoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeTrajectoryError::MissingLength => write!(f, "missing trajectory length prefix"),
+            DecodeTrajectoryError::WrongLength { points, bytes } => {
+                write!(f, "{} bytes can't hold {} trajectory points", bytes, points)
+            }
+        }
+    }
+}
+
+impl Error for DecodeTrajectoryError {}
+
+// Why a deserialized State was rejected
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeStateError {
+    // The die range low..high has no faces to roll
+    EmptyDieRange { low: i32, high: i32 },
+    // The cached dice reach time steps that don't fit in an i32
+    CachesOutOfRange,
+}
+
+impl fmt::Display for DecodeStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeStateError::EmptyDieRange { low, high } => {
+                write!(f, "die range {}..{} is empty", low, high)
+            }
+            DecodeStateError::CachesOutOfRange => write!(f, "dice caches run past the i32 clock"),
+        }
+    }
+}
+
+impl Error for DecodeStateError {}
@@ -0,0 +1,37 @@
+mod builder;
+mod checkpoint;
+mod coupled;
+mod die;
+mod divergence;
+#[cfg(feature = "collect")]
+mod ensemble;
+mod error;
+mod registry;
+mod schedule;
+mod state;
+mod stats;
+#[cfg(feature = "collect")]
+mod trajectory;
+mod transition;
+mod vm;
+
+pub mod testing;
+
+pub use builder::StateBuilder;
+pub use checkpoint::{Checkpoint, CheckpointDiff};
+pub use coupled::CoupledTransition;
+pub use die::{DieSource, KeyedDie, SequentialDie, UniformDie, WeightedDie};
+#[cfg(feature = "collect")]
+pub use divergence::{compare_perturbed, divergence_grid, divergence_over_time};
+pub use divergence::{divergence, first_divergence};
+#[cfg(feature = "collect")]
+pub use ensemble::{distribution_at, run_ensemble, run_ensemble_seeded, variance_growth};
+pub use error::{DecodeStateError, DecodeTrajectoryError, ParseTransitionError, StepError};
+pub use registry::TransitionRegistry;
+pub use schedule::TransitionSchedule;
+pub use state::{State, State64, StateF64};
+pub use stats::{Stats, Welford};
+#[cfg(feature = "collect")]
+pub use trajectory::Trajectory;
+pub use transition::{Transition, Transition64, TransitionF64};
+pub use vm::Op;
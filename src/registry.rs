@@ -0,0 +1,62 @@
+use crate::{ParseTransitionError, Transition};
+use std::collections::HashMap;
+
+// Transitions registered under names at runtime, for frontends that pick them from data
+pub struct TransitionRegistry<M = i32> {
+    transitions: HashMap<String, Transition<M>>,
+}
+
+// Only the default macrostate type has built-ins, so Default preloads them as new does
+impl Default for TransitionRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M> TransitionRegistry<M> {
+    // A registry with nothing registered
+    pub fn empty() -> Self {
+        TransitionRegistry {
+            transitions: HashMap::new(),
+        }
+    }
+
+    // Register the transition under the given name, replacing any already registered under it
+    pub fn register(&mut self, name: &str, transition: Transition<M>) {
+        self.transitions.insert(name.to_string(), transition);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Transition<M>> {
+        self.transitions.get(name)
+    }
+
+    // The registered names, in no particular order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.transitions.keys().map(String::as_str)
+    }
+}
+
+impl TransitionRegistry {
+    // A registry preloaded with the built-in transitions that take no parameters, each under
+    // its own name: idle, random_step, random_step_checked, and xor_step
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        for transition in [
+            Transition::idle(),
+            Transition::random_step(),
+            Transition::random_step_checked(),
+            Transition::xor_step(),
+        ] {
+            let name = transition.name().unwrap_or_default().to_string();
+            registry.register(&name, transition);
+        }
+        registry
+    }
+
+    // Parse a spec such as "record:5", as accepted by Transition::try_from, and register the
+    // transition under the given name, so that a config file can define transitions by name
+    pub fn register_spec(&mut self, name: &str, spec: &str) -> Result<(), ParseTransitionError> {
+        self.register(name, Transition::try_from(spec)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,24 @@
+use crate::Transition;
+use std::ops::Range;
+
+// Chooses the transition for each time step, replacing ad hoc branching on t
+pub struct TransitionSchedule<M = i32> {
+    // Transitions for the steps starting in each range; earlier entries take precedence
+    entries: Vec<(Range<i32>, Transition<M>)>,
+    // The transition for steps not covered by any range
+    default: Transition<M>,
+}
+
+impl<M> TransitionSchedule<M> {
+    pub fn new(entries: Vec<(Range<i32>, Transition<M>)>, default: Transition<M>) -> Self {
+        TransitionSchedule { entries, default }
+    }
+
+    // The transition for the step from t to t + 1
+    pub fn get(&self, t: i32) -> &Transition<M> {
+        self.entries
+            .iter()
+            .find(|(range, _)| range.contains(&t))
+            .map_or(&self.default, |(_, transition)| transition)
+    }
+}
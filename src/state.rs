@@ -0,0 +1,1557 @@
+#[cfg(feature = "collect")]
+use crate::Trajectory;
+use crate::{
+    Checkpoint, DecodeStateError, DieSource, KeyedDie, SequentialDie, StepError, Transition,
+    TransitionSchedule, UniformDie, WeightedDie, Welford,
+};
+#[cfg(feature = "collect")]
+use num_traits::PrimInt;
+use num_traits::ToPrimitive;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "collect")]
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+// Clones share the die source, which is a pure function of t, so they roll identical dice
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "StateData<M>")]
+pub struct State<M = i32> {
+    // The current time step
+    t: i32,
+    // The current macrostate
+    macrostate: M,
+    // The macrostate the state was created with
+    origin: M,
+    // The earliest and latest time steps reached since then
+    explored: (i32, i32),
+    // A cache of the dice to roll in the future
+    future_dice: Vec<i32>,
+    // A cache of the dice to roll in the past
+    past_dice: Vec<i32>,
+    // The seed from which roll_die was built
+    microstate_seed: u64,
+    // The faces low..high of the die built from the seed
+    die_range: (i32, i32),
+    // Whether roll_die is the uniform die built from the seed and range, rather than a custom
+    // source, so that reseeding can rebuild it
+    #[serde(skip)]
+    seeded: bool,
+    // How many dice were rolled rather than taken from a cache
+    #[serde(skip)]
+    rng_calls: u64,
+    // The times from the oldest cached past die to the end of the newest cached future one,
+    // kept separately from the caches so that invariant_ok can check stepping against them
+    #[serde(skip)]
+    cached_span: (i32, i32),
+    // Running statistics of the macrostates reached by stepping forward, if they are tracked
+    #[serde(skip)]
+    running_stats: Option<RunningStats<M>>,
+    // The least and greatest macrostates reached since tracking started, if it has
+    #[serde(skip)]
+    extent: Option<Extent<M>>,
+    // How to render the macrostate in the trace logged at each step, if it is included
+    #[serde(skip)]
+    describe: Option<fn(&M) -> String>,
+    // The (t, index) of each transition step_forward_by chose, with t the time step it
+    // started from, so that step_backward_by can undo the same one
+    #[serde(skip)]
+    choices: Vec<(i32, usize)>,
+    // Where the dice preloaded into the future cache run out, for warn-unconsumed-dice
+    #[serde(skip)]
+    preloaded: Preloaded,
+    // Specifies the initial state of all the dice
+    #[serde(skip)]
+    roll_die: Dice<M>,
+}
+
+// A state whose macrostate has room for very long walks
+pub type State64 = State<i64>;
+
+// A state with a continuous macrostate. Floating-point transitions round, so unlike with
+// integer macrostates, rewinding is not guaranteed to return exactly to an earlier macrostate
+pub type StateF64 = State<f64>;
+
+// A Welford accumulator along with how to turn a macrostate into a value for it
+type RunningStats<M> = (Welford, fn(&M) -> f64);
+
+// The least and greatest macrostates reached, along with how to tell if one is less than another
+type Extent<M> = ((M, M), fn(&M, &M) -> bool);
+
+// The time step up to which the future cache holds preloaded dice, if it was ever preloaded
+// Clones don't inherit it: their future dice were preloaded into the original, not them
+#[derive(Default)]
+struct Preloaded(Option<i32>);
+
+impl Clone for Preloaded {
+    fn clone(&self) -> Self {
+        Preloaded(None)
+    }
+}
+
+// A die that also sees the macrostate of the step being taken
+type StatefulDie<M> = Arc<dyn Fn(u64, &M) -> i32 + Send + Sync>;
+
+#[derive(Clone)]
+enum Dice<M> {
+    // Rolls depend only on the time step
+    Plain(Arc<dyn DieSource>),
+    // Rolls also depend on the macrostate the step leaves from
+    Stateful(StatefulDie<M>),
+}
+
+impl<M> Dice<M> {
+    fn roll(&self, t: u64, macrostate: &M) -> i32 {
+        match self {
+            Dice::Plain(source) => source.roll(t),
+            Dice::Stateful(source) => source(t, macrostate),
+        }
+    }
+}
+
+// The input at which a die source rolls the step from t to t + 1, defined for negative t too
+fn die_time(t: i32) -> u64 {
+    t as i64 as u64
+}
+
+// How a step came by its die, for tracing
+fn die_origin(supplied: bool, cached: bool) -> &'static str {
+    match (supplied, cached) {
+        (true, _) => "supplied",
+        (false, true) => "cached",
+        (false, false) => "rolled",
+    }
+}
+
+// The serialized fields of a State, from which roll_die is rebuilt
+#[derive(Deserialize)]
+struct StateData<M> {
+    t: i32,
+    macrostate: M,
+    origin: M,
+    explored: (i32, i32),
+    future_dice: Vec<i32>,
+    past_dice: Vec<i32>,
+    microstate_seed: u64,
+    die_range: (i32, i32),
+}
+
+// Deserializing checks what building the die and the cached span would otherwise panic on
+impl<M> TryFrom<StateData<M>> for State<M> {
+    type Error = DecodeStateError;
+
+    fn try_from(data: StateData<M>) -> Result<Self, Self::Error> {
+        let (low, high) = data.die_range;
+        if low >= high {
+            return Err(DecodeStateError::EmptyDieRange { low, high });
+        }
+        let span_end = |len: usize, sign: i64| {
+            i64::try_from(len)
+                .ok()
+                .and_then(|len| i32::try_from(i64::from(data.t) + sign * len).ok())
+                .ok_or(DecodeStateError::CachesOutOfRange)
+        };
+        let cached_span = (
+            span_end(data.past_dice.len(), -1)?,
+            span_end(data.future_dice.len(), 1)?,
+        );
+        Ok(State {
+            t: data.t,
+            macrostate: data.macrostate,
+            origin: data.origin,
+            explored: data.explored,
+            future_dice: data.future_dice,
+            past_dice: data.past_dice,
+            microstate_seed: data.microstate_seed,
+            die_range: data.die_range,
+            seeded: true,
+            rng_calls: 0,
+            cached_span,
+            running_stats: None,
+            extent: None,
+            describe: None,
+            choices: Vec::new(),
+            preloaded: Preloaded::default(),
+            roll_die: Dice::Plain(Arc::new(UniformDie::with_range(
+                data.microstate_seed,
+                data.die_range.0,
+                data.die_range.1,
+            ))),
+        })
+    }
+}
+
+// Equality is over what a simulation can observe: the time, macrostate, dice caches, and
+// the seed and range the dice are rolled from. The die source itself is a closure and can't be
+// compared, so states with the same seed but different custom sources compare equal
+impl<M: PartialEq> PartialEq for State<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.t == other.t
+            && self.macrostate == other.macrostate
+            && self.past_dice == other.past_dice
+            && self.future_dice == other.future_dice
+            && self.microstate_seed == other.microstate_seed
+            && self.die_range == other.die_range
+    }
+}
+
+// Debug shows the same fields that equality compares
+impl<M: fmt::Debug> fmt::Debug for State<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("State")
+            .field("t", &self.t)
+            .field("macrostate", &self.macrostate)
+            .field("past_dice", &self.past_dice)
+            .field("future_dice", &self.future_dice)
+            .field("microstate_seed", &self.microstate_seed)
+            .field("die_range", &self.die_range)
+            .finish_non_exhaustive()
+    }
+}
+
+// With the warn-unconsumed-dice feature, warn when a state is dropped before stepping forward
+// through all the dice preloaded by from_dice, append_future or StateBuilder::preload_future.
+// Future dice cached by stepping backward aren't reported, nor are clones of a preloaded state
+#[cfg(feature = "warn-unconsumed-dice")]
+impl<M> Drop for State<M> {
+    fn drop(&mut self) {
+        if let Some(unreplayed) = self.unreplayed_preloaded() {
+            log::warn!(
+                "state dropped at t = {} with {} preloaded dice never replayed",
+                self.t,
+                unreplayed
+            );
+        }
+    }
+}
+
+impl<M> State<M> {
+    // How many of the preloaded dice the state has yet to step forward through, if any
+    #[cfg(any(test, feature = "warn-unconsumed-dice"))]
+    fn unreplayed_preloaded(&self) -> Option<i32> {
+        self.preloaded
+            .0
+            .map(|until| until - self.explored.1)
+            .filter(|&unreplayed| unreplayed > 0)
+    }
+}
+
+// Display the dice on both sides of the macrostate
+impl<M: fmt::Display> fmt::Display for State<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display_window(usize::MAX).fmt(f)
+    }
+}
+
+// Display at most radius of the cached dice on each side of the macrostate
+struct DisplayWindow<'a, M> {
+    state: &'a State<M>,
+    radius: usize,
+}
+
+impl<M: fmt::Display> fmt::Display for DisplayWindow<'_, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state;
+        write!(f, "State at t={}: ...", state.t)?;
+        let skipped = state.past_dice.len().saturating_sub(self.radius);
+        for &die in &state.past_dice[skipped..] {
+            write!(f, " {}", die)?;
+        }
+        write!(f, " ({}) ", state.macrostate)?;
+        for &die in state.future_dice.iter().rev().take(self.radius) {
+            write!(f, "{} ", die)?;
+        }
+        write!(f, "...")
+    }
+}
+
+// The die sources don't depend on the macrostate, so they live on the default State
+impl State {
+    pub fn uniform_rolls(microstate_seed: u64) -> Box<dyn Fn(u64) -> i32 + Send + Sync> {
+        Self::uniform_rolls_range(microstate_seed, 0, 6)
+    }
+
+    // Roll uniformly in low..high; panics if the range is empty
+    pub fn uniform_rolls_range(
+        microstate_seed: u64,
+        low: i32,
+        high: i32,
+    ) -> Box<dyn Fn(u64) -> i32 + Send + Sync> {
+        let die = UniformDie::with_range(microstate_seed, low, high);
+        Box::new(move |t| die.roll(t))
+    }
+
+    // Roll like uniform_rolls but from one cached stream, which is much faster for long forward
+    // runs and much slower for random access in time; see SequentialDie. Use uniform_rolls when
+    // rewinding past the dice cache
+    pub fn sequential_rolls(microstate_seed: u64) -> Box<dyn Fn(u64) -> i32 + Send + Sync> {
+        let die = SequentialDie::new(microstate_seed);
+        Box::new(move |t| die.roll(t))
+    }
+
+    // Roll face i of 0..weights.len() with probability proportional to weights[i]
+    // Panics unless the weights are finite, non-negative, and not all zero
+    pub fn weighted_rolls(
+        microstate_seed: u64,
+        weights: &[f64],
+    ) -> Box<dyn Fn(u64) -> i32 + Send + Sync> {
+        let die = WeightedDie::new(microstate_seed, weights);
+        Box::new(move |t| die.roll(t))
+    }
+}
+
+impl<M: Clone> State<M> {
+    // Create a new state with the given macrostate
+    pub fn new(macrostate: M) -> Self {
+        Self::from_seed(macrostate, rand::rng().random())
+    }
+
+    // Create a new state whose dice are fully determined by the given seed
+    pub fn from_seed(macrostate: M, microstate_seed: u64) -> Self {
+        Self::from_uniform_die(macrostate, UniformDie::new(microstate_seed))
+    }
+
+    // Create a new state rolling a die with faces low..high; panics if the range is empty
+    pub fn new_with_die(macrostate: M, low: i32, high: i32) -> Self {
+        let microstate_seed: u64 = rand::rng().random();
+        Self::from_uniform_die(
+            macrostate,
+            UniformDie::with_range(microstate_seed, low, high),
+        )
+    }
+
+    pub(crate) fn from_uniform_die(macrostate: M, die: UniformDie) -> Self {
+        State {
+            t: 0,
+            origin: macrostate.clone(),
+            explored: (0, 0),
+            macrostate,
+            future_dice: Vec::new(),
+            past_dice: Vec::new(),
+            microstate_seed: die.seed,
+            die_range: (die.low, die.high),
+            seeded: true,
+            rng_calls: 0,
+            cached_span: (0, 0),
+            running_stats: None,
+            extent: None,
+            describe: None,
+            choices: Vec::new(),
+            preloaded: Preloaded::default(),
+            roll_die: Dice::Plain(Arc::new(die)),
+        }
+    }
+
+    // Create a new state that replays the given dice in order, rolling randomly once they run out
+    pub fn from_dice(macrostate: M, future_dice: Vec<i32>) -> Self {
+        Self::new(macrostate).replaying(future_dice)
+    }
+
+    // Replace the future cache so the given dice are replayed in order
+    pub(crate) fn replaying(mut self, mut future_dice: Vec<i32>) -> Self {
+        // The future cache is a stack, so the first die to replay goes last
+        future_dice.reverse();
+        self.future_dice = future_dice;
+        self.resync_cached_span();
+        self.mark_preloaded();
+        self
+    }
+
+    // Note that the future cache, as far as it now runs, holds preloaded dice
+    fn mark_preloaded(&mut self) {
+        if !self.future_dice.is_empty() {
+            let until = self.t + self.future_dice.len() as i32;
+            self.preloaded.0 = Some(self.preloaded.0.map_or(until, |old| old.max(until)));
+        }
+    }
+
+    // The span of times covered by the caches as they stand
+    fn span_of_caches(&self) -> (i32, i32) {
+        (
+            self.t - self.past_dice.len() as i32,
+            self.t + self.future_dice.len() as i32,
+        )
+    }
+
+    // Reset the tracked span after replacing the caches other than by stepping
+    fn resync_cached_span(&mut self) {
+        self.cached_span = self.span_of_caches();
+    }
+
+    // Start keeping a running mean and variance of the macrostate, from the current one onwards
+    // Only forward steps are counted: stepping backward leaves the statistics alone, so a walk
+    // that retraces its steps counts each revisited macrostate again when it steps forward
+    pub fn track_running_stats(&mut self)
+    where
+        M: ToPrimitive,
+    {
+        let to_f64: fn(&M) -> f64 = |macrostate| macrostate.to_f64().unwrap_or(f64::NAN);
+        let mut welford = Welford::default();
+        welford.push(to_f64(&self.macrostate));
+        self.running_stats = Some((welford, to_f64));
+    }
+
+    // The mean and population variance of the macrostates tracked so far, or None if
+    // track_running_stats was never called
+    pub fn running_stats(&self) -> Option<(f64, f64)> {
+        self.running_stats
+            .as_ref()
+            .map(|(welford, _)| (welford.mean(), welford.variance()))
+    }
+
+    // Add the current macrostate to the running statistics, if they are tracked
+    fn observe(&mut self) {
+        if let Some((welford, to_f64)) = &mut self.running_stats {
+            welford.push(to_f64(&self.macrostate));
+        }
+    }
+
+    // Start tracking the least and greatest macrostates reached, in either direction, from the
+    // current one onwards. Like the running statistics this is opt-in, so that stepping
+    // doesn't need macrostates to be ordered, and it isn't serialized
+    pub fn track_extent(&mut self)
+    where
+        M: PartialOrd,
+    {
+        let less: fn(&M, &M) -> bool = |a, b| a < b;
+        self.extent = Some(((self.macrostate.clone(), self.macrostate.clone()), less));
+    }
+
+    // The least and greatest macrostates reached since track_extent, or None if untracked
+    pub fn extent(&self) -> Option<(M, M)> {
+        self.extent.as_ref().map(|(extent, _)| extent.clone())
+    }
+
+    // Widen the extent to include the current macrostate, if it is tracked
+    fn widen_extent(&mut self) {
+        if let Some(((low, high), less)) = &mut self.extent {
+            if less(&self.macrostate, low) {
+                *low = self.macrostate.clone();
+            } else if less(high, &self.macrostate) {
+                *high = self.macrostate.clone();
+            }
+        }
+    }
+
+    // Include the macrostate in the trace logged at each step, which otherwise leaves it out so
+    // that stepping doesn't need macrostates to be Debug
+    pub fn trace_macrostates(&mut self)
+    where
+        M: fmt::Debug,
+    {
+        self.describe = Some(|macrostate| format!("{:?}", macrostate));
+    }
+
+    // Check that stepping has kept the caches in step with time: every fresh roll must extend
+    // the tracked span by one die, and every cached die must be replayed rather than lost
+    pub fn invariant_ok(&self) -> bool {
+        self.cached_span == self.span_of_caches()
+    }
+
+    pub fn peturbed(old_state: &Self) -> Self {
+        let (low, high) = old_state.die_range;
+        let die = UniformDie::with_range(rand::rng().random(), low, high);
+        let mut state = Self::from_uniform_die(old_state.macrostate.clone(), die);
+        state.t = old_state.t;
+        state.explored = (state.t, state.t);
+        state.resync_cached_span();
+        state
+    }
+
+    // Like peturbed, but keep the cached future dice so they are replayed exactly
+    // Only the dice that were never rolled diverge: those beyond the end of the future cache,
+    // and every past die, since the past cache is dropped and rerolled from the new seed
+    pub fn peturbed_keep_future(old_state: &Self) -> Self {
+        let mut state = Self::peturbed(old_state);
+        state.future_dice = old_state.future_dice.clone();
+        state.resync_cached_span();
+        state
+    }
+
+    // Extend the past cache further back with known dice, given in chronological order, so the
+    // last of them is the die for the step just before the oldest cached one. Stepping backward
+    // then replays them instead of rolling
+    pub fn prepend_past_dice(&mut self, dice: &[i32]) {
+        self.past_dice.splice(0..0, dice.iter().copied());
+        self.resync_cached_span();
+    }
+
+    // Take the cached past dice, in chronological order, and restart the clock at t=0 from the
+    // current macrostate, which becomes the new origin and tracked extent. This is destructive: the
+    // state can no longer step back into the drained past, and uncached dice are rolled at the
+    // new time steps, so they differ from those the old clock would have rolled
+    pub fn drain_past(&mut self) -> Vec<i32> {
+        let old_t = std::mem::replace(&mut self.t, 0);
+        self.explored = (0, 0);
+        self.origin = self.macrostate.clone();
+        for (t, _) in &mut self.choices {
+            *t -= old_t;
+        }
+        if let Some(until) = &mut self.preloaded.0 {
+            *until -= old_t;
+        }
+        if let Some((extent, _)) = &mut self.extent {
+            *extent = (self.macrostate.clone(), self.macrostate.clone());
+        }
+        let past_dice = std::mem::take(&mut self.past_dice);
+        self.resync_cached_span();
+        past_dice
+    }
+
+    // Extend the future cache with more dice, given in chronological order, to be replayed after
+    // the ones already cached. The cache is a stack with the next die last, so they are stored
+    // reversed at the bottom of it
+    pub fn append_future(&mut self, dice: &[i32]) {
+        self.future_dice.splice(0..0, dice.iter().rev().copied());
+        self.resync_cached_span();
+        self.mark_preloaded();
+    }
+
+    // Roll future dice from a new seed, keeping the cached past dice but dropping the future ones,
+    // which came from the old seed. Like peturbed, this models an outside perturbation: stepping
+    // back across the reseed point is only exact while the past cache lasts, since uncached
+    // past dice are rolled from the new seed too. Panics if the state rolls a custom source,
+    // as from with_die_source or from_key, which a seed can't rebuild
+    pub fn reseed(&mut self, new_seed: u64) {
+        assert!(self.seeded, "can't reseed a state with a custom die source");
+        let (low, high) = self.die_range;
+        self.microstate_seed = new_seed;
+        self.roll_die = Dice::Plain(Arc::new(UniformDie::with_range(new_seed, low, high)));
+        self.future_dice.clear();
+        self.preloaded.0 = None;
+        self.resync_cached_span();
+    }
+
+    // Create a new state whose dice come from a custom source
+    // The stored microstate_seed is zero, so serializing such a state does not preserve its dice
+    pub fn with_die_source(macrostate: M, source: impl DieSource + 'static) -> Self {
+        State {
+            t: 0,
+            origin: macrostate.clone(),
+            explored: (0, 0),
+            macrostate,
+            future_dice: Vec::new(),
+            past_dice: Vec::new(),
+            microstate_seed: 0,
+            die_range: (0, 6),
+            seeded: false,
+            rng_calls: 0,
+            cached_span: (0, 0),
+            running_stats: None,
+            extent: None,
+            describe: None,
+            choices: Vec::new(),
+            preloaded: Preloaded::default(),
+            roll_die: Dice::Plain(Arc::new(source)),
+        }
+    }
+
+    // Create a new state whose dice come from a full 256-bit key, for ensembles too large for
+    // u64 seeds to avoid collisions. As with with_die_source, serializing does not preserve the dice
+    pub fn from_key(macrostate: M, key: [u8; 32]) -> Self {
+        Self::with_die_source(macrostate, KeyedDie { key })
+    }
+
+    // Create a new state whose die at each step also depends on the macrostate
+    // A forward step from t rolls source(t, m) on the macrostate m it leaves, but a backward
+    // step onto t that isn't cached can only see the macrostate it leaves, the one after the
+    // forward step. Rewinding is exact only if both give the same die; see
+    // stateful_die_is_consistent. As with with_die_source, serializing does not preserve the dice
+    pub fn with_stateful_die(
+        macrostate: M,
+        source: impl Fn(u64, &M) -> i32 + Send + Sync + 'static,
+    ) -> Self {
+        let mut state = Self::with_die_source(macrostate, |_| 0);
+        state.roll_die = Dice::Stateful(Arc::new(source));
+        state
+    }
+
+    // Check that a stateful die rolls the same die on both sides of each sampled step
+    pub fn stateful_die_is_consistent(
+        source: impl Fn(u64, &M) -> i32,
+        transition: &Transition<M>,
+        samples: &[(u64, M)],
+    ) -> bool {
+        samples.iter().all(|(t, macrostate)| {
+            let die = source(*t, macrostate);
+            source(*t, &(transition.evolve_forward)(macrostate, die)) == die
+        })
+    }
+
+    // The current time step
+    pub fn t(&self) -> i32 {
+        self.t
+    }
+
+    // The earliest through the latest time steps reached since the state was created, which
+    // only widens, as stepping back over explored time steps doesn't shrink it
+    pub fn time_range(&self) -> RangeInclusive<i32> {
+        self.explored.0..=self.explored.1
+    }
+
+    // The current macrostate
+    pub fn macrostate(&self) -> &M {
+        &self.macrostate
+    }
+
+    // The macrostate the state was created with
+    pub fn origin(&self) -> &M {
+        &self.origin
+    }
+
+    // The cached past dice in chronological order, so the most recent die is last
+    pub fn past_dice(&self) -> &[i32] {
+        &self.past_dice
+    }
+
+    // The cached future dice in reverse chronological order, so the next die is last
+    pub fn future_dice(&self) -> &[i32] {
+        &self.future_dice
+    }
+
+    // The seed from which the dice are rolled
+    pub fn microstate_seed(&self) -> u64 {
+        self.microstate_seed
+    }
+
+    // How many steps rolled a fresh die rather than replaying a cached one
+    pub fn rng_calls(&self) -> u64 {
+        self.rng_calls
+    }
+
+    // Take a snapshot of the whole state, to jump back to later with restore
+    pub fn checkpoint(&self) -> Checkpoint<M> {
+        Checkpoint {
+            state: self.clone(),
+        }
+    }
+
+    // Return to a snapshot, wherever the state has been since. Any preloaded dice the state
+    // hasn't yet replayed stay preloaded, since they are still ahead in the snapshot's cache
+    pub fn restore(&mut self, checkpoint: &Checkpoint<M>) {
+        let explored = self.explored.1;
+        let preloaded = std::mem::take(&mut self.preloaded);
+        *self = checkpoint.state.clone();
+        self.preloaded.0 = preloaded.0.filter(|&until| explored < until);
+    }
+}
+
+impl<M: fmt::Display> State<M> {
+    // Display at most radius dice on each side of the macrostate, for compact output
+    pub fn display_window(&self, radius: usize) -> impl fmt::Display + '_ {
+        DisplayWindow {
+            state: self,
+            radius,
+        }
+    }
+
+    // Render the states one per row under a shared time axis, so that each column holds the
+    // same time step in every row. Columns alternate between times, where a row shows its
+    // macrostate if it is at that time, and the steps between them, showing any cached die
+    pub fn display_aligned(states: &[&Self]) -> String {
+        let first = states.iter().map(|s| s.t - s.past_dice.len() as i32).min();
+        let last = states
+            .iter()
+            .map(|s| s.t + s.future_dice.len() as i32)
+            .max();
+        let (Some(first), Some(last)) = (first, last) else {
+            return String::new();
+        };
+        // Cell 2 * (t - first) is time t, and the cell after it is the step from t to t + 1
+        let cells = 2 * (last - first) as usize + 1;
+        let mut rows = vec![vec![String::new(); cells]];
+        for t in first..=last {
+            rows[0][2 * (t - first) as usize] = t.to_string();
+        }
+        for state in states {
+            let mut row = vec![String::new(); cells];
+            let now = 2 * (state.t - first) as usize;
+            row[now] = format!("({})", state.macrostate);
+            for (i, die) in state.past_dice.iter().rev().enumerate() {
+                row[now - 1 - 2 * i] = die.to_string();
+            }
+            for (i, die) in state.future_dice.iter().rev().enumerate() {
+                row[now + 1 + 2 * i] = die.to_string();
+            }
+            rows.push(row);
+        }
+        let width = rows.iter().flatten().map(String::len).max().unwrap_or(0);
+        let mut aligned = String::new();
+        for row in rows {
+            let line: Vec<_> = row.iter().map(|cell| format!("{:>width$}", cell)).collect();
+            aligned.push_str(line.join(" ").trim_end());
+            aligned.push('\n');
+        }
+        aligned
+    }
+}
+
+impl<M: Clone + PartialEq> State<M> {
+    // The die for the step from t to t + 1, rolled if it isn't cached
+    fn next_die(&self) -> i32 {
+        match self.future_dice.last() {
+            Some(&die) => die,
+            None => self.roll_die.roll(die_time(self.t), &self.macrostate),
+        }
+    }
+
+    // The die for the step from t - 1 to t, rolled if it isn't cached
+    fn prev_die(&self) -> i32 {
+        match self.past_dice.last() {
+            Some(&die) => die,
+            None => self.roll_die.roll(die_time(self.t - 1), &self.macrostate),
+        }
+    }
+
+    // The die for the step from t to t + 1: the cached one if t is within either cache, so
+    // that it agrees with what stepping would use, and otherwise rolled without counting in
+    // rng_calls. Any i64 is accepted, though stepping can only reach the i32 time steps. As
+    // with peek_future, a stateful die is rolled on the current macrostate
+    pub fn die_at(&self, t: i64) -> i32 {
+        let (past, future) = (self.past_dice.len() as i64, self.future_dice.len() as i64);
+        match t.checked_sub(i64::from(self.t)) {
+            Some(offset) if (-past..0).contains(&offset) => {
+                self.past_dice[(past + offset) as usize]
+            }
+            Some(offset) if (0..future).contains(&offset) => {
+                self.future_dice[(future - 1 - offset) as usize]
+            }
+            // Too far from t to be cached, whether or not the offset fits in an i64
+            _ => self.roll_die.roll(t as u64, &self.macrostate),
+        }
+    }
+
+    // Move to t + 1, having applied the next die with the named transition, if it has a name
+    fn advance(&mut self, die: i32, macrostate: M, name: Option<&str>) {
+        self.advance_with(die, macrostate, name, false);
+    }
+
+    // Move to t + 1, having applied the given die, which replaces any cached one if supplied
+    fn advance_with(&mut self, die: i32, macrostate: M, name: Option<&str>, supplied: bool) {
+        let cached = self.future_dice.pop().is_some();
+        if !cached {
+            self.rng_calls += u64::from(!supplied);
+            self.cached_span.1 += 1;
+        }
+        self.past_dice.push(die);
+        self.macrostate = macrostate;
+        self.widen_extent();
+        self.observe();
+        self.forget_choices_from(self.t);
+        self.t += 1;
+        self.explored.1 = self.explored.1.max(self.t);
+        self.trace_step("forward", name, die, die_origin(supplied, cached));
+        debug_assert!(self.invariant_ok(), "dice caches out of step with t");
+    }
+
+    // Move to t - 1, having undone the previous die with the named transition, if it has a name
+    fn retreat(&mut self, die: i32, macrostate: M, name: Option<&str>) {
+        self.retreat_with(die, macrostate, name, false);
+    }
+
+    // Move to t - 1, having undone the given die, which replaces any cached one if supplied
+    fn retreat_with(&mut self, die: i32, macrostate: M, name: Option<&str>, supplied: bool) {
+        let cached = self.past_dice.pop().is_some();
+        if !cached {
+            self.rng_calls += u64::from(!supplied);
+            self.cached_span.0 -= 1;
+        }
+        self.future_dice.push(die);
+        self.macrostate = macrostate;
+        self.widen_extent();
+        self.t -= 1;
+        self.forget_choices_from(self.t);
+        self.explored.0 = self.explored.0.min(self.t);
+        self.trace_step("backward", name, die, die_origin(supplied, cached));
+        debug_assert!(self.invariant_ok(), "dice caches out of step with t");
+    }
+
+    // Log a step at trace level; the arguments aren't formatted unless that level is enabled
+    // The transition's name is included when it has one, and the resulting macrostate after
+    // trace_macrostates
+    fn trace_step(&self, direction: &str, name: Option<&str>, die: i32, origin: &str) {
+        log::trace!(
+            "stepped {} to t={}{} with {} die {}{}",
+            direction,
+            self.t,
+            match name {
+                Some(name) => format!(" by {}", name),
+                None => String::new(),
+            },
+            origin,
+            die,
+            match self.describe {
+                Some(describe) => format!(", macrostate {}", describe(&self.macrostate)),
+                None => String::new(),
+            }
+        );
+    }
+
+    // Replace the macrostate from outside the dynamics, such as to inject a measurement
+    // Like reseed, this breaks exact reversibility at the current time step: stepping back from
+    // here retraces the dice, but from the new macrostate, so it won't reach the old history.
+    // The dice caches and time step are kept, and any tracked extent widens to cover it
+    pub fn set_macrostate(&mut self, macrostate: M) {
+        self.macrostate = macrostate;
+        self.widen_extent();
+    }
+
+    // Like set_macrostate, but consuming and returning the state for chaining
+    pub fn with_macrostate(mut self, macrostate: M) -> Self {
+        self.set_macrostate(macrostate);
+        self
+    }
+
+    // Step forward using the given evolution in place of a transition
+    pub(crate) fn apply_forward(&mut self, evolve: impl FnOnce(&M, i32) -> M) {
+        let die = self.next_die();
+        let macrostate = evolve(&self.macrostate, die);
+        self.advance(die, macrostate, None);
+    }
+
+    // Step backward using the given evolution in place of a transition
+    pub(crate) fn apply_backward(&mut self, evolve: impl FnOnce(&M, i32) -> M) {
+        let die = self.prev_die();
+        let macrostate = evolve(&self.macrostate, die);
+        self.retreat(die, macrostate, None);
+    }
+
+    // Step the state forward in time
+    pub fn step_forward(&mut self, transition: &Transition<M>) {
+        let die = self.next_die();
+        let macrostate = (transition.evolve_forward)(&self.macrostate, die);
+        self.advance(die, macrostate, transition.name());
+    }
+
+    // Step the state backward in time
+    pub fn step_backward(&mut self, transition: &Transition<M>) {
+        let die = self.prev_die();
+        let macrostate = (transition.evolve_backward)(&self.macrostate, die);
+        transition.debug_assert_reversible(&macrostate, die);
+        self.retreat(die, macrostate, transition.name());
+    }
+
+    // Step forward as with step_forward, returning the die that was applied
+    pub fn step_forward_returning(&mut self, transition: &Transition<M>) -> i32 {
+        let die = self.next_die();
+        let macrostate = (transition.evolve_forward)(&self.macrostate, die);
+        self.advance(die, macrostate, transition.name());
+        die
+    }
+
+    // Step backward as with step_backward, returning the die that was undone
+    pub fn step_backward_returning(&mut self, transition: &Transition<M>) -> i32 {
+        let die = self.prev_die();
+        let macrostate = (transition.evolve_backward)(&self.macrostate, die);
+        transition.debug_assert_reversible(&macrostate, die);
+        self.retreat(die, macrostate, transition.name());
+        die
+    }
+
+    // Step forward with the given die in place of the state's own, such as one just rolled by
+    // another state to correlate the two. The die is cached like any other, replacing the one
+    // that was cached for this step if there was one
+    pub fn step_forward_with_die(&mut self, transition: &Transition<M>, die: i32) {
+        let macrostate = (transition.evolve_forward)(&self.macrostate, die);
+        self.advance_with(die, macrostate, transition.name(), true);
+    }
+
+    // Step backward with the given die, which undoes step_forward_with_die given the same die
+    pub fn step_backward_with_die(&mut self, transition: &Transition<M>, die: i32) {
+        let macrostate = (transition.evolve_backward)(&self.macrostate, die);
+        transition.debug_assert_reversible(&macrostate, die);
+        self.retreat_with(die, macrostate, transition.name(), true);
+    }
+
+    // Step the state forward in time, or leave it unchanged if the step fails
+    pub fn try_step_forward(&mut self, transition: &Transition<M>) -> Result<(), StepError> {
+        let die = self.next_die();
+        let macrostate = transition.try_forward(&self.macrostate, die)?;
+        self.advance(die, macrostate, transition.name());
+        Ok(())
+    }
+
+    // Step the state backward in time, or leave it unchanged if the step fails
+    pub fn try_step_backward(&mut self, transition: &Transition<M>) -> Result<(), StepError> {
+        let die = self.prev_die();
+        let macrostate = transition.try_backward(&self.macrostate, die)?;
+        transition.debug_assert_reversible(&macrostate, die);
+        self.retreat(die, macrostate, transition.name());
+        Ok(())
+    }
+
+    // Step forward until pred holds, returning how many steps that took, or MaxSteps if it
+    // still doesn't after max_steps. Steps are taken with try_step_forward, so a failed step
+    // stops early with its error. Either way the steps taken can be rewound exactly
+    pub fn step_until(
+        &mut self,
+        transition: &Transition<M>,
+        pred: impl Fn(&Self) -> bool,
+        max_steps: u32,
+    ) -> Result<u32, StepError> {
+        for steps in 0..max_steps {
+            if pred(self) {
+                return Ok(steps);
+            }
+            self.try_step_forward(transition)?;
+        }
+        if pred(self) {
+            Ok(max_steps)
+        } else {
+            Err(StepError::MaxSteps)
+        }
+    }
+
+    // Step forward until the macrostate is back at the origin, the macrostate the state was
+    // created with or last drained to, returning how many steps that took, or None if it hasn't
+    // within max_steps. The state needn't start at the origin. Rewind to undo the steps
+    pub fn has_returned_to_origin(
+        &mut self,
+        transition: &Transition<M>,
+        max_steps: u32,
+    ) -> Option<u32> {
+        (1..=max_steps).find(|_| {
+            self.step_forward(transition);
+            self.macrostate == self.origin
+        })
+    }
+
+    // Step forward or backward until reaching the target time, reusing any cached dice
+    pub fn goto(&mut self, transition: &Transition<M>, target_t: i32) {
+        while self.t < target_t {
+            self.step_forward(transition);
+        }
+        while self.t > target_t {
+            self.step_backward(transition);
+        }
+    }
+
+    // An independent copy of the state moved to time t, leaving self where it is
+    // The copy shares the cached dice and die source, so it retraces the same history on the way
+    // to t, and can then diverge under other transitions
+    pub fn fork_at(&self, transition: &Transition<M>, t: i32) -> Self {
+        let mut fork = self.clone();
+        fork.goto(transition, t);
+        fork
+    }
+
+    // Step from the current time to to_t, in either direction, using the scheduled transition
+    // for each step
+    pub fn run_schedule(&mut self, schedule: &TransitionSchedule<M>, to_t: i32) {
+        while self.t < to_t {
+            self.step_forward(schedule.get(self.t));
+        }
+        while self.t > to_t {
+            self.step_backward(schedule.get(self.t - 1));
+        }
+    }
+
+    // Step forward with even on even time steps and odd on odd ones, for two-phase maps
+    pub fn step_forward_parity(&mut self, even: &Transition<M>, odd: &Transition<M>) {
+        let transition = if self.t.rem_euclid(2) == 0 { even } else { odd };
+        self.step_forward(transition);
+    }
+
+    // Undo step_forward_parity, choosing by the parity of the step being undone, from t - 1
+    pub fn step_backward_parity(&mut self, even: &Transition<M>, odd: &Transition<M>) {
+        let transition = if (self.t - 1).rem_euclid(2) == 0 {
+            even
+        } else {
+            odd
+        };
+        self.step_backward(transition);
+    }
+
+    // Step forward with whichever of the transitions the chooser picks, by index, for the
+    // current state, recording the choice for step_backward_by. Panics if the index is out of
+    // bounds
+    pub fn step_forward_by(
+        &mut self,
+        transitions: &[&Transition<M>],
+        chooser: impl Fn(&Self) -> usize,
+    ) {
+        let index = chooser(self);
+        self.step_forward(transitions[index]);
+        self.choices.push((self.t - 1, index));
+    }
+
+    // Undo step_forward_by with the transition it recorded choosing for the previous step. If
+    // that step was taken by another step method, nothing was recorded, so the chooser is asked
+    // again, of the state after the step but with its time step wound back to the step's start,
+    // t - 1. That is only exact if the choice depends just on the time step, as with
+    // step_forward_parity, or on properties of the macrostate that the chosen transition never
+    // changes
+    pub fn step_backward_by(
+        &mut self,
+        transitions: &[&Transition<M>],
+        chooser: impl Fn(&Self) -> usize,
+    ) {
+        let index = match self.choices.last() {
+            Some(&(t, index)) if t == self.t - 1 => {
+                self.choices.pop();
+                index
+            }
+            _ => {
+                self.t -= 1;
+                let index = chooser(self);
+                self.t += 1;
+                index
+            }
+        };
+        self.step_backward(transitions[index]);
+    }
+
+    // Drop the recorded choices for the steps from time step t onwards, once any step crosses
+    // them, since they may no longer be the transitions those steps were taken with
+    fn forget_choices_from(&mut self, t: i32) {
+        while self.choices.last().is_some_and(|&(start, _)| start >= t) {
+            self.choices.pop();
+        }
+    }
+
+    // Step the state forward n times, reserving room for the new past dice up front
+    pub fn step_forward_n(&mut self, transition: &Transition<M>, n: usize) {
+        self.past_dice.reserve(n);
+        for _ in 0..n {
+            self.step_forward(transition);
+        }
+    }
+
+    // Step forward n times as with step_forward_n, but once the future cache runs out, roll the
+    // rest of the dice directly instead of asking next_die for each. This is only a few percent
+    // faster, since rolling the die dominates rather than checking the cache; for a real
+    // speedup use a cheaper die source such as sequential_rolls
+    pub fn fast_forward(&mut self, transition: &Transition<M>, n: usize) {
+        let cached = n.min(self.future_dice.len());
+        for _ in 0..cached {
+            self.step_forward(transition);
+        }
+        self.past_dice.reserve(n - cached);
+        for _ in cached..n {
+            let die = self.roll_die.roll(die_time(self.t), &self.macrostate);
+            let macrostate = (transition.evolve_forward)(&self.macrostate, die);
+            self.advance(die, macrostate, transition.name());
+        }
+    }
+
+    // Step the state backward n times, reserving room for the new future dice up front
+    pub fn step_backward_n(&mut self, transition: &Transition<M>, n: usize) {
+        self.future_dice.reserve(n);
+        for _ in 0..n {
+            self.step_backward(transition);
+        }
+    }
+
+    // Consume the state, lazily yielding the macrostate after each forward step
+    pub fn iter_forward(mut self, transition: Transition<M>) -> impl Iterator<Item = M> {
+        std::iter::from_fn(move || {
+            self.step_forward(&transition);
+            Some(self.macrostate.clone())
+        })
+    }
+
+    // Consume the state, lazily yielding the macrostate after each backward step
+    pub fn iter_backward(mut self, transition: Transition<M>) -> impl Iterator<Item = M> {
+        std::iter::from_fn(move || {
+            self.step_backward(&transition);
+            Some(self.macrostate.clone())
+        })
+    }
+}
+
+// The methods that collect their results, which need the collect feature
+#[cfg(feature = "collect")]
+impl<M: Clone + PartialEq> State<M> {
+    // The next n dice step_forward would use, from the future cache and then rolled at t + k,
+    // without changing the state or counting the rolls in rng_calls. With a stateful die the
+    // uncached dice are rolled on the current macrostate, which the steps before them would
+    // have changed, so they are only a guess
+    pub fn peek_future(&self, n: usize) -> Vec<i32> {
+        let cached = self.future_dice.iter().rev().take(n).copied();
+        let rolled = (self.future_dice.len()..n).map(|k| {
+            self.roll_die
+                .roll(die_time(self.t + k as i32), &self.macrostate)
+        });
+        cached.chain(rolled).collect()
+    }
+
+    // Step forward until the dynamics from the call first recur exactly, returning the period,
+    // or None if they haven't within max_steps. A period p needs the macrostate back at its
+    // value at the call and the next p dice to repeat the p dice taken since, so that the next
+    // cycle retraces the first. With constant dice this is the cycle length of the map itself;
+    // with random dice a match is a coincidence that the dice after it needn't keep up, and is
+    // likely for short periods. Rewind to undo the steps
+    pub fn detect_period(&mut self, transition: &Transition<M>, max_steps: i32) -> Option<i32> {
+        let start = self.macrostate.clone();
+        let mut dice = Vec::new();
+        (1..=max_steps).find(|&steps| {
+            dice.push(self.step_forward_returning(transition));
+            self.macrostate == start && self.peek_future(steps as usize) == dice
+        })
+    }
+
+    // Step forward the given number of times, recording the macrostate at each time step
+    pub fn run(&mut self, transition: &Transition<M>, steps: i32) -> Trajectory<M> {
+        let mut points = vec![(self.t, self.macrostate.clone())];
+        for _ in 0..steps {
+            self.step_forward(transition);
+            points.push((self.t, self.macrostate.clone()));
+        }
+        Trajectory { points }
+    }
+
+    // Step backward until the past cache is empty, recording the macrostate at each time step
+    // For a state that has only stepped forward since it was created, this returns it to t=0
+    pub fn rewind(&mut self, transition: &Transition<M>) -> Trajectory<M> {
+        let mut points = Vec::with_capacity(self.past_dice.len() + 1);
+        points.push((self.t, self.macrostate.clone()));
+        while !self.past_dice.is_empty() {
+            self.step_backward(transition);
+            points.push((self.t, self.macrostate.clone()));
+        }
+        Trajectory { points }
+    }
+
+    // Run forward n steps, then step back to where the state started, returning the forward
+    // trajectory and whether the state came back exactly. The dice rolled on the way are now
+    // cached as future dice, so coming back means the same time, macrostate, and past cache,
+    // with the original future cache still to be replayed next
+    pub fn roundtrip_demo(&mut self, transition: &Transition<M>, n: i32) -> (Trajectory<M>, bool) {
+        let start = self.clone();
+        let trajectory = self.run(transition, n);
+        self.step_backward_n(transition, n.max(0) as usize);
+        let restored = self.t == start.t
+            && self.macrostate == start.macrostate
+            && self.past_dice == start.past_dice
+            && self.future_dice.ends_with(&start.future_dice);
+        (trajectory, restored)
+    }
+
+    // Step forward the given number of times, counting how often each macrostate is reached
+    pub fn occupancy(&mut self, transition: &Transition<M>, steps: i32) -> BTreeMap<M, u32>
+    where
+        M: Ord,
+    {
+        let mut counts = BTreeMap::new();
+        for _ in 0..steps {
+            self.step_forward(transition);
+            *counts.entry(self.macrostate.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    // Step forward the given number of times, rendering the whole state after each step as a
+    // frame that can be shown on its own
+    pub fn frames(&mut self, transition: &Transition<M>, steps: i32) -> Vec<String>
+    where
+        M: fmt::Display,
+    {
+        (0..steps)
+            .map(|_| {
+                self.step_forward(transition);
+                self.to_string()
+            })
+            .collect()
+    }
+
+    // Run as with run, rendering the trajectory as JSON
+    pub fn run_to_json(&mut self, transition: &Transition<M>, steps: i32) -> String
+    where
+        M: PrimInt + fmt::Display,
+    {
+        self.run(transition, steps).to_json()
+    }
+}
+
+impl State {
+    // How far the macrostate has moved from the one the state was created with, wrapping like
+    // random_step does
+    pub fn net_displacement(&self) -> i32 {
+        self.macrostate.wrapping_sub(self.origin)
+    }
+
+    // The total distance covered by the cached past steps, assuming each die is the
+    // displacement of its step, as with random_step. Summed as a u64, so that neither an
+    // i32::MIN die nor a long walk overflows
+    pub fn path_length(&self) -> u64 {
+        self.past_dice
+            .iter()
+            .map(|die| u64::from(die.unsigned_abs()))
+            .sum()
+    }
+}
+
+impl State<(i32, i32)> {
+    // Create a new state on a 2D lattice rolling the faces 0..36 that random_step_2d needs
+    pub fn new_2d(macrostate: (i32, i32)) -> Self {
+        Self::from_seed_2d(macrostate, rand::rng().random())
+    }
+
+    // Like new_2d, but with the dice fully determined by the given seed
+    pub fn from_seed_2d(macrostate: (i32, i32), microstate_seed: u64) -> Self {
+        Self::from_uniform_die(macrostate, UniformDie::with_range(microstate_seed, 0, 36))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clone_steps_forward_identically() {
+        let transition = Transition::random_step();
+        let mut original = State::from_seed(0, 9);
+        original.step_forward_n(&transition, 5);
+        original.step_backward_n(&transition, 2);
+        let mut branch = original.clone();
+        for _ in 0..20 {
+            original.step_forward(&transition);
+            branch.step_forward(&transition);
+            assert_eq!(original, branch);
+        }
+    }
+
+    #[test]
+    fn from_dice_replays_in_order_then_rolls() {
+        let transition = Transition::random_step();
+        let mut state = State::from_dice(0, vec![4, 1, 5]);
+        let dice: Vec<i32> = (0..3)
+            .map(|_| state.step_forward_returning(&transition))
+            .collect();
+        assert_eq!(dice, [4, 1, 5]);
+        assert_eq!(*state.macrostate(), 10);
+        assert_eq!(state.rng_calls(), 0);
+        state.step_forward(&transition);
+        assert_eq!(state.rng_calls(), 1, "the fourth die should be rolled");
+    }
+
+    #[test]
+    fn dice_into_negative_time_are_replayed() {
+        let transition = Transition::random_step();
+        let mut state = State::from_seed(0, 11);
+        let backward: Vec<i32> = (0..5)
+            .map(|_| state.step_backward_returning(&transition))
+            .collect();
+        assert_eq!(state.t(), -5);
+        let forward: Vec<i32> = (0..5)
+            .map(|_| state.step_forward_returning(&transition))
+            .collect();
+        assert_eq!(
+            forward.iter().rev().collect::<Vec<_>>(),
+            backward.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(*state.macrostate(), 0);
+
+        // A fresh state rolls the same dice for negative time steps
+        let fresh = State::from_seed(0, 11);
+        for (k, &die) in forward.iter().enumerate() {
+            assert_eq!(fresh.die_at(k as i64 - 5), die);
+        }
+    }
+
+    #[test]
+    fn random_step_2d_moves_in_both_dimensions() {
+        let transition = Transition::random_step_2d();
+        let mut state = State::from_seed_2d((0, 0), 5);
+        state.step_forward_n(&transition, 100);
+        let (x, y) = *state.macrostate();
+        assert!(x > 0 && y > 0, "walk reached ({x}, {y})");
+        state.step_backward_n(&transition, 100);
+        assert_eq!(*state.macrostate(), (0, 0));
+    }
+
+    #[test]
+    fn rng_calls_counts_only_fresh_rolls() {
+        let transition = Transition::random_step();
+        let mut state = State::from_seed(0, 2);
+        state.step_forward_n(&transition, 3);
+        assert_eq!(state.rng_calls(), 3);
+        // Three cached past dice, then two fresh ones before t=0
+        state.step_backward_n(&transition, 5);
+        assert_eq!(state.rng_calls(), 5);
+        // Five cached future dice, then one fresh one past t=3
+        state.step_forward_n(&transition, 6);
+        assert_eq!(state.rng_calls(), 6);
+        assert_eq!(state.t(), 4);
+    }
+
+    #[test]
+    fn unordered_macrostates_can_step() {
+        #[derive(Clone, PartialEq)]
+        struct Unordered(i32);
+        let transition = Transition::from_closures(
+            |m: &Unordered, die| Unordered(m.0 + die),
+            |m: &Unordered, die| Unordered(m.0 - die),
+        );
+        let mut state = State::from_seed(Unordered(0), 1);
+        state.step_forward_n(&transition, 10);
+        state.step_backward_n(&transition, 10);
+        assert!(*state.macrostate() == Unordered(0));
+        assert!(state.extent().is_none());
+    }
+
+    #[test]
+    fn extent_is_opt_in_and_covers_both_directions() {
+        let transition = Transition::random_step();
+        let mut state = State::from_dice(0, vec![3, 2]);
+        assert_eq!(state.extent(), None);
+        state.track_extent();
+        state.step_forward_n(&transition, 2);
+        state.step_backward_n(&transition, 4);
+        assert_eq!(state.extent(), Some((*state.macrostate(), 5)));
+    }
+
+    #[test]
+    fn net_displacement_wraps() {
+        let mut state = State::from_dice(i32::MAX, vec![2]);
+        state.step_forward(&Transition::random_step());
+        assert_eq!(*state.macrostate(), i32::MIN + 1);
+        assert_eq!(state.net_displacement(), 2);
+    }
+
+    #[test]
+    fn fast_forward_matches_step_forward_n() {
+        let transition = Transition::random_step();
+        let mut fast = State::from_dice(0, vec![1, -2]);
+        let mut slow = fast.clone();
+        fast.fast_forward(&transition, 20);
+        slow.step_forward_n(&transition, 20);
+        assert_eq!(fast.macrostate(), slow.macrostate());
+        assert_eq!(fast.past_dice, slow.past_dice);
+        assert_eq!(fast.rng_calls(), slow.rng_calls());
+        fast.step_backward_n(&transition, 20);
+        assert_eq!(*fast.macrostate(), 0);
+    }
+
+    #[test]
+    fn running_stats_are_none_until_tracked() {
+        let mut state = State::from_dice(0, vec![3, 3]);
+        assert_eq!(state.running_stats(), None);
+        state.track_running_stats();
+        state.step_forward_n(&Transition::random_step(), 2);
+        assert_eq!(state.running_stats(), Some((3.0, 6.0)));
+    }
+
+    #[test]
+    fn has_returned_to_origin_measures_from_the_origin() {
+        let transition = Transition::random_step();
+        let mut state = State::from_dice(0, vec![2, 1, -3, 1, -1]);
+        state.step_forward(&transition);
+        assert_eq!(state.has_returned_to_origin(&transition, 10), Some(2));
+        assert_eq!(state.t(), 3);
+    }
+
+    #[test]
+    fn step_backward_by_undoes_the_recorded_choice() {
+        let (up, down) = (Transition::record(10), Transition::record(-1));
+        let transitions = [&up, &down];
+        let chooser = |state: &State| usize::from(*state.macrostate() >= 5);
+        let mut state = State::from_seed(0, 1);
+        state.step_forward_by(&transitions, chooser);
+        state.step_forward_by(&transitions, chooser);
+        assert_eq!(*state.macrostate(), 9);
+        state.step_backward_by(&transitions, chooser);
+        state.step_backward_by(&transitions, chooser);
+        assert_eq!(*state.macrostate(), 0);
+    }
+
+    #[test]
+    fn step_backward_by_forgets_choices_that_other_steps_cross() {
+        let (up, down) = (Transition::record(10), Transition::record(-1));
+        let transitions = [&up, &down];
+        let mut state = State::from_seed(0, 1);
+        state.step_forward_by(&transitions, |_| 0);
+        state.step_backward(&up);
+        state.step_forward(&down);
+        state.step_backward_by(&transitions, |state| usize::from(state.t() == 0));
+        assert_eq!(*state.macrostate(), 0);
+    }
+
+    #[test]
+    fn only_unreplayed_preloaded_dice_are_reported() {
+        let transition = Transition::random_step();
+        let mut state = State::from_dice(0, vec![1, 2, 3]);
+        assert_eq!(state.unreplayed_preloaded(), Some(3));
+        state.step_forward(&transition);
+        let checkpoint = state.checkpoint();
+        assert_eq!(state.clone().unreplayed_preloaded(), None);
+        state.step_forward_n(&transition, 2);
+        assert_eq!(state.unreplayed_preloaded(), None);
+        state.step_backward_n(&transition, 3);
+        assert_eq!(state.unreplayed_preloaded(), None);
+        state.restore(&checkpoint);
+        assert_eq!(state.unreplayed_preloaded(), None);
+
+        let mut rewound = State::from_seed(0, 1);
+        rewound.step_backward_n(&transition, 4);
+        rewound.append_future(&[5, 6]);
+        assert_eq!(rewound.unreplayed_preloaded(), Some(2));
+        let checkpoint = rewound.checkpoint();
+        rewound.step_forward(&transition);
+        rewound.restore(&checkpoint);
+        assert_eq!(rewound.unreplayed_preloaded(), Some(2));
+    }
+
+    #[test]
+    fn json_round_trip_rolls_the_same_dice() {
+        let transition = Transition::random_step();
+        let mut state = State::from_seed(0, 17);
+        state.step_forward_n(&transition, 3);
+        state.step_backward(&transition);
+        let json = serde_json::to_string(&state).unwrap();
+        let mut restored: State = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, state);
+        for _ in 0..5 {
+            assert_eq!(
+                restored.step_forward_returning(&transition),
+                state.step_forward_returning(&transition)
+            );
+        }
+    }
+
+    #[test]
+    fn deserializing_rejects_bad_states() {
+        let json = serde_json::to_string(&State::from_seed(0, 17)).unwrap();
+        let empty_range = json.replace("\"die_range\":[0,6]", "\"die_range\":[3,3]");
+        let error = serde_json::from_str::<State>(&empty_range).unwrap_err();
+        assert!(error.to_string().contains("die range 3..3 is empty"));
+        let past_the_clock = json
+            .replace("\"t\":0", &format!("\"t\":{}", i32::MAX))
+            .replace("\"future_dice\":[]", "\"future_dice\":[1]");
+        let error = serde_json::from_str::<State>(&past_the_clock).unwrap_err();
+        assert!(error.to_string().contains("i32 clock"));
+    }
+
+    #[test]
+    fn goto_there_and_back_restores_the_state() {
+        let transition = Transition::random_step();
+        let mut state = State::from_seed(0, 12);
+        state.goto(&transition, 50);
+        assert_eq!(state.t(), 50);
+        state.goto(&transition, 0);
+        assert_eq!(*state.macrostate(), 0);
+
+        state.goto(&transition, 7);
+        let mut at_a = state.clone();
+        state.goto(&transition, -20);
+        state.goto(&transition, 7);
+        assert_eq!(
+            (state.t(), state.macrostate()),
+            (at_a.t(), at_a.macrostate())
+        );
+        state.step_forward_n(&transition, 10);
+        at_a.step_forward_n(&transition, 10);
+        assert_eq!(state.macrostate(), at_a.macrostate());
+    }
+
+    #[test]
+    fn run_schedule_steps_each_way_with_the_scheduled_transitions() {
+        let schedule =
+            TransitionSchedule::new(vec![(0..3, Transition::record(10))], Transition::record(1));
+        let mut state = State::from_seed(0, 1);
+        state.run_schedule(&schedule, 5);
+        assert_eq!((state.t(), *state.macrostate()), (5, 32));
+        state.run_schedule(&schedule, -2);
+        assert_eq!((state.t(), *state.macrostate()), (-2, -2));
+        state.run_schedule(&schedule, 0);
+        assert_eq!(*state.macrostate(), 0);
+    }
+
+    #[test]
+    fn path_length_counts_extreme_dice() {
+        let mut state = State::from_dice(0, vec![i32::MIN, i32::MIN, -3, 2]);
+        state.step_forward_n(&Transition::random_step(), 4);
+        assert_eq!(state.path_length(), (1 << 32) + 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "custom die source")]
+    fn reseed_refuses_custom_die_sources() {
+        let mut state = State::with_die_source(0, State::weighted_rolls(1, &[0.0, 1.0]));
+        state.reseed(2);
+    }
+
+    // Collects the trace logged on the current thread, so other tests' steps don't mix in
+    struct TraceLog;
+
+    static TRACES: std::sync::Mutex<Vec<(std::thread::ThreadId, String)>> =
+        std::sync::Mutex::new(Vec::new());
+
+    impl log::Log for TraceLog {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            if record.level() == log::Level::Trace {
+                let line = (std::thread::current().id(), record.args().to_string());
+                TRACES.lock().unwrap().push(line);
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn trace_names_the_transition_and_the_macrostate() {
+        log::set_logger(&TraceLog).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+        let mut state = State::from_dice(0, vec![4]);
+        state.trace_macrostates();
+        state.step_forward(&Transition::random_step());
+        state.step_backward(&Transition::random_step());
+        let here = std::thread::current().id();
+        let traces: Vec<String> = TRACES
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(thread, _)| *thread == here)
+            .map(|(_, line)| line.clone())
+            .collect();
+        assert_eq!(
+            traces,
+            [
+                "stepped forward to t=1 by random_step with cached die 4, macrostate 4",
+                "stepped backward to t=0 by random_step with cached die 4, macrostate 0",
+            ]
+        );
+    }
+
+    #[cfg(feature = "collect")]
+    #[test]
+    fn detect_period_finds_the_ring_length_with_constant_dice() {
+        let mut state = State::from_dice(0, vec![1; 20]);
+        assert_eq!(
+            state.detect_period(&Transition::modular_step(5), 10),
+            Some(5)
+        );
+        let mut state = State::from_dice(0, vec![2; 20]);
+        assert_eq!(
+            state.detect_period(&Transition::modular_step(4), 10),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn die_at_handles_the_cache_boundaries_and_i64_extremes() {
+        let transition = Transition::random_step();
+        let mut state = State::from_dice(0, vec![1, 2, 3, 4, 5]);
+        state.step_forward_n(&transition, 3);
+        state.prepend_past_dice(&[0]);
+        let die = UniformDie::new(state.microstate_seed());
+        let rolled = |t: i64| die.roll(t as u64);
+        assert_eq!(state.die_at(-1), 0);
+        assert_eq!(state.die_at(-2), rolled(-2));
+        assert_eq!(state.die_at(2), 3);
+        assert_eq!(state.die_at(3), 4);
+        assert_eq!(state.die_at(4), 5);
+        assert_eq!(state.die_at(5), rolled(5));
+        assert_eq!(state.die_at(i64::MIN), rolled(i64::MIN));
+        assert_eq!(state.die_at(i64::MAX), rolled(i64::MAX));
+        assert_eq!(state.rng_calls(), 0);
+        state.goto(&transition, -1);
+        assert_eq!(state.die_at(i64::MAX), rolled(i64::MAX));
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+// Summary statistics of a collection of macrostates, such as an ensemble's final values
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stats {
+    pub mean: f64,
+    // The population variance
+    pub variance: f64,
+    // The entropy, in nats, of the histogram that bins identical macrostates together
+    pub shannon_entropy: f64,
+}
+
+impl Stats {
+    // The mean and variance are NaN for an empty slice
+    pub fn from_macrostates(macrostates: &[i32]) -> Stats {
+        let n = macrostates.len() as f64;
+        let mean = macrostates.iter().map(|&m| m as f64).sum::<f64>() / n;
+        let variance = macrostates
+            .iter()
+            .map(|&m| (m as f64 - mean).powi(2))
+            .sum::<f64>()
+            / n;
+
+        let mut histogram = HashMap::new();
+        for &m in macrostates {
+            *histogram.entry(m).or_insert(0u64) += 1;
+        }
+        let shannon_entropy = histogram
+            .values()
+            .map(|&count| {
+                let p = count as f64 / n;
+                p * (1.0 / p).ln()
+            })
+            .sum();
+
+        Stats {
+            mean,
+            variance,
+            shannon_entropy,
+        }
+    }
+}
+
+// An online mean and population variance, updated one value at a time with Welford's method
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Welford {
+    count: u64,
+    mean: f64,
+    // The sum of squared deviations from the running mean
+    m2: f64,
+}
+
+impl Welford {
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    // The number of values pushed so far
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    // NaN if no values have been pushed
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 { f64::NAN } else { self.mean }
+    }
+
+    // The population variance; NaN if no values have been pushed
+    pub fn variance(&self) -> f64 {
+        self.m2 / self.count as f64
+    }
+}
@@ -0,0 +1,37 @@
+// Helpers for checking custom transitions, e.g. from a downstream fuzz test
+use crate::{State, Transition};
+use std::fmt;
+
+// Step forward then backward, panicking unless the state is exactly restored
+// If the step's die had to be rolled, it stays cached as the next future die
+pub fn assert_roundtrip<M>(state: &mut State<M>, transition: &Transition<M>)
+where
+    M: Clone + PartialEq + fmt::Debug,
+{
+    let (t, macrostate) = (state.t(), state.macrostate().clone());
+    let past_dice = state.past_dice().to_vec();
+    let mut future_dice = state.future_dice().to_vec();
+
+    state.step_forward(transition);
+    if future_dice.is_empty() {
+        future_dice.extend(state.past_dice().last());
+    }
+    state.step_backward(transition);
+
+    assert_eq!(state.t(), t, "round trip changed t");
+    assert_eq!(
+        *state.macrostate(),
+        macrostate,
+        "round trip changed the macrostate"
+    );
+    assert_eq!(
+        state.past_dice(),
+        past_dice,
+        "round trip changed the past dice"
+    );
+    assert_eq!(
+        state.future_dice(),
+        future_dice,
+        "round trip changed the future dice"
+    );
+}
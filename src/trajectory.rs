@@ -0,0 +1,153 @@
+use crate::{DecodeTrajectoryError, Transition};
+use num_traits::{PrimInt, WrappingSub};
+use std::fmt::{self, Write};
+use std::ops::Range;
+
+// The macrostate recorded at each time step of a run
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trajectory<M = i32> {
+    // The (t, macrostate) pairs in the order they were visited
+    pub points: Vec<(i32, M)>,
+}
+
+impl<M: fmt::Display> Trajectory<M> {
+    // Render the trajectory as CSV, one (t, macrostate) pair per row
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("t,macrostate\n");
+        for (t, macrostate) in &self.points {
+            writeln!(csv, "{},{}", t, macrostate).unwrap();
+        }
+        csv
+    }
+}
+
+impl<M: PrimInt + fmt::Display> Trajectory<M> {
+    // Render the trajectory as {"points":[{"t":0,"m":0},...]}, with integer macrostates
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"points\":[");
+        for (i, (t, macrostate)) in self.points.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            write!(json, "{{\"t\":{},\"m\":{}}}", t, macrostate).unwrap();
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+impl<M: WrappingSub> Trajectory<M> {
+    // The change in macrostate over each step, one fewer than there are points
+    // For random_step these are the dice; in general each is the macrostate after minus before,
+    // wrapping so that steps across the ends of the range don't overflow
+    pub fn deltas(&self) -> Vec<M> {
+        self.points
+            .windows(2)
+            .map(|pair| pair[1].1.wrapping_sub(&pair[0].1))
+            .collect()
+    }
+}
+
+impl<M: PartialEq> Trajectory<M> {
+    // Recover the dice that produced the trajectory, searching the given faces at each step for
+    // one that takes each point to the next under the transition. Returns None if some step has
+    // no such die. Where several dice fit, as when the transition ignores the die, the lowest wins
+    pub fn infer_dice(&self, transition: &Transition<M>, faces: Range<i32>) -> Option<Vec<i32>> {
+        self.points
+            .windows(2)
+            .map(|pair| {
+                faces
+                    .clone()
+                    .find(|&die| (transition.evolve_forward)(&pair[0].1, die) == pair[1].1)
+            })
+            .collect()
+    }
+}
+
+impl Trajectory {
+    // Encode as a little-endian u64 count of points followed by each point's t and macrostate
+    // as little-endian i32s
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + 8 * self.points.len());
+        bytes.extend_from_slice(&(self.points.len() as u64).to_le_bytes());
+        for &(t, macrostate) in &self.points {
+            bytes.extend_from_slice(&t.to_le_bytes());
+            bytes.extend_from_slice(&macrostate.to_le_bytes());
+        }
+        bytes
+    }
+
+    // Decode the format written by to_bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeTrajectoryError> {
+        let (prefix, body) = bytes
+            .split_first_chunk::<8>()
+            .ok_or(DecodeTrajectoryError::MissingLength)?;
+        let points = u64::from_le_bytes(*prefix);
+        if points.checked_mul(8) != Some(body.len() as u64) {
+            return Err(DecodeTrajectoryError::WrongLength {
+                points,
+                bytes: body.len(),
+            });
+        }
+        let int = |bytes: &[u8]| i32::from_le_bytes(bytes.try_into().unwrap());
+        let points = body
+            .chunks_exact(8)
+            .map(|point| (int(&point[..4]), int(&point[4..])))
+            .collect();
+        Ok(Trajectory { points })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deltas_wrap_across_the_ends_of_the_range() {
+        let trajectory = Trajectory {
+            points: vec![(0, i32::MAX - 1), (1, i32::MIN), (2, i32::MAX)],
+        };
+        assert_eq!(trajectory.deltas(), vec![2, -1]);
+    }
+
+    #[test]
+    fn bytes_round_trip_exactly() {
+        let trajectory = Trajectory {
+            points: vec![(-3, i32::MIN), (0, 0), (1, -1), (i32::MAX, i32::MAX)],
+        };
+        let bytes = trajectory.to_bytes();
+        assert_eq!(bytes.len(), 8 + 8 * 4);
+        assert_eq!(Trajectory::from_bytes(&bytes), Ok(trajectory));
+        let empty = Trajectory { points: vec![] };
+        assert_eq!(Trajectory::from_bytes(&empty.to_bytes()), Ok(empty));
+    }
+
+    #[test]
+    fn from_bytes_rejects_bad_lengths() {
+        assert_eq!(
+            Trajectory::from_bytes(&[1, 0, 0]),
+            Err(DecodeTrajectoryError::MissingLength)
+        );
+        let mut bytes = Trajectory {
+            points: vec![(0, 1), (1, 2)],
+        }
+        .to_bytes();
+        bytes.pop();
+        assert_eq!(
+            Trajectory::from_bytes(&bytes),
+            Err(DecodeTrajectoryError::WrongLength {
+                points: 2,
+                bytes: 15
+            })
+        );
+        let mut huge = u64::MAX.to_le_bytes().to_vec();
+        huge.extend([0; 8]);
+        assert_eq!(
+            Trajectory::from_bytes(&huge),
+            Err(DecodeTrajectoryError::WrongLength {
+                points: u64::MAX,
+                bytes: 8
+            })
+        );
+    }
+}
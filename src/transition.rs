@@ -0,0 +1,1042 @@
+use crate::{Op, ParseTransitionError, StepError};
+use num_traits::{CheckedAdd, CheckedSub, WrappingAdd, WrappingSub};
+use std::fmt;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+
+// Maps a macrostate and a die roll to the next macrostate
+type Evolve<M> = Box<dyn Fn(&M, i32) -> M + Send + Sync>;
+// Like Evolve, but reports macrostates that can't be represented instead of producing them
+type TryEvolve<M> = Box<dyn Fn(&M, i32) -> Result<M, StepError> + Send + Sync>;
+
+pub struct Transition<M = i32> {
+    // Specifies how the macrostate evolves forward in time, given a die roll
+    pub(crate) evolve_forward: Evolve<M>,
+    // Specifies how the macrostate evolves backward in time, given a die roll
+    // We must have evolve_backward(evolve_forward(x, r), r) = x for all x and r
+    pub(crate) evolve_backward: Evolve<M>,
+    // Optional fallible forms of the above, used by the try_step_* methods
+    try_evolve_forward: Option<TryEvolve<M>>,
+    try_evolve_backward: Option<TryEvolve<M>>,
+    // A human-readable name for logging, set by the built-in constructors and named
+    name: Option<String>,
+    // Whether evolve_backward undoes evolve_forward exactly, so that debug builds can check it
+    exact: bool,
+}
+
+// Shows the name, since the evolutions themselves are opaque closures
+impl<M> fmt::Debug for Transition<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name.as_deref().unwrap_or("<anonymous>"))
+    }
+}
+
+// Transitions on the macrostate of a State64
+pub type Transition64 = Transition<i64>;
+
+// Transitions on the macrostate of a StateF64
+pub type TransitionF64 = Transition<f64>;
+
+impl<M> Transition<M> {
+    fn new(
+        evolve_forward: impl Fn(&M, i32) -> M + Send + Sync + 'static,
+        evolve_backward: impl Fn(&M, i32) -> M + Send + Sync + 'static,
+    ) -> Self {
+        Transition {
+            evolve_forward: Box::new(evolve_forward),
+            evolve_backward: Box::new(evolve_backward),
+            try_evolve_forward: None,
+            try_evolve_backward: None,
+            name: None,
+            exact: true,
+        }
+    }
+
+    // Build a transition from a custom forward and backward evolution
+    // They must satisfy backward(forward(m, die), die) == m; check samples with is_reversible.
+    // In debug builds, step_backward also checks each step it takes
+    pub fn from_closures(
+        forward: impl Fn(&M, i32) -> M + Send + Sync + 'static,
+        backward: impl Fn(&M, i32) -> M + Send + Sync + 'static,
+    ) -> Self {
+        Self::new(forward, backward)
+    }
+
+    // Like from_closures, but with a name to show when the transition is logged
+    pub fn named(
+        name: impl Into<String>,
+        forward: impl Fn(&M, i32) -> M + Send + Sync + 'static,
+        backward: impl Fn(&M, i32) -> M + Send + Sync + 'static,
+    ) -> Self {
+        Self::new(forward, backward).with_name(name)
+    }
+
+    fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    // The name given to the transition, if any
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    // Mark the transition as only approximately reversible, such as one with floating-point
+    // arithmetic, so that debug builds don't check each backward step undoes forward exactly.
+    // Check it with is_reversible_approx instead
+    pub fn approximate(mut self) -> Self {
+        self.exact = false;
+        self
+    }
+
+    fn with_try_evolve(
+        mut self,
+        try_evolve_forward: impl Fn(&M, i32) -> Result<M, StepError> + Send + Sync + 'static,
+        try_evolve_backward: impl Fn(&M, i32) -> Result<M, StepError> + Send + Sync + 'static,
+    ) -> Self {
+        self.try_evolve_forward = Some(Box::new(try_evolve_forward));
+        self.try_evolve_backward = Some(Box::new(try_evolve_backward));
+        self
+    }
+
+    // Swap the forward and backward evolutions, so stepping forward runs the dynamics in reverse
+    pub fn inverse(self) -> Self {
+        Transition {
+            evolve_forward: self.evolve_backward,
+            evolve_backward: self.evolve_forward,
+            try_evolve_forward: self.try_evolve_backward,
+            try_evolve_backward: self.try_evolve_forward,
+            name: self.name.map(|name| format!("{}.inverse()", name)),
+            exact: self.exact,
+        }
+    }
+
+    // Evolve forward, falling back to the infallible form if there is no fallible one
+    pub(crate) fn try_forward(&self, macrostate: &M, die: i32) -> Result<M, StepError> {
+        match &self.try_evolve_forward {
+            Some(try_evolve) => try_evolve(macrostate, die),
+            None => Ok((self.evolve_forward)(macrostate, die)),
+        }
+    }
+
+    // Evolve backward, falling back to the infallible form if there is no fallible one
+    pub(crate) fn try_backward(&self, macrostate: &M, die: i32) -> Result<M, StepError> {
+        match &self.try_evolve_backward {
+            Some(try_evolve) => try_evolve(macrostate, die),
+            None => Ok((self.evolve_backward)(macrostate, die)),
+        }
+    }
+}
+
+impl<M: Clone + 'static> Transition<M> {
+    pub fn idle() -> Self {
+        Self::new(
+            |macrostate, _| macrostate.clone(),
+            |macrostate, _| macrostate.clone(),
+        )
+        .with_name("idle")
+    }
+
+    // Leave the macrostate alone but push each die onto the log, popping it when stepping back
+    // The log is behind a Mutex rather than a RefCell because transitions are shared across threads
+    pub fn logging(log: Arc<Mutex<Vec<i32>>>) -> Self {
+        let log_back = log.clone();
+        Self::new(
+            move |macrostate, die| {
+                log.lock().unwrap().push(die);
+                macrostate.clone()
+            },
+            move |macrostate, die| {
+                let logged = log_back.lock().unwrap().pop();
+                debug_assert_eq!(logged, Some(die), "log is out of sync with the dice");
+                macrostate.clone()
+            },
+        )
+        .with_name("logging")
+    }
+}
+
+impl<M> Transition<M>
+where
+    M: Clone + CheckedAdd + CheckedSub + WrappingAdd + WrappingSub + Send + Sync + 'static,
+{
+    // The die is converted into the macrostate type before being added
+    // Arithmetic wraps around on overflow, which is still exactly reversible. For long runs this
+    // tracks relative motion correctly even once the absolute position has wrapped
+    pub fn random_step() -> Self
+    where
+        M: From<i32>,
+    {
+        Self::new(
+            |macrostate, dice| macrostate.wrapping_add(&M::from(dice)),
+            |macrostate, dice| macrostate.wrapping_sub(&M::from(dice)),
+        )
+        .with_name("random_step")
+    }
+
+    // Like random_step, but overflow panics in debug builds, and the try_step_* methods report
+    // it as StepError::Overflow instead of stepping
+    pub fn random_step_checked() -> Self
+    where
+        M: From<i32>,
+    {
+        Self::new(
+            |macrostate, dice| macrostate.clone() + M::from(dice),
+            |macrostate, dice| macrostate.clone() - M::from(dice),
+        )
+        .with_try_evolve(
+            |macrostate, dice| checked(macrostate.checked_add(&M::from(dice))),
+            |macrostate, dice| checked(macrostate.checked_sub(&M::from(dice))),
+        )
+        .with_name("random_step_checked")
+    }
+
+    // Add val to the macrostate at every step, ignoring the die; wraps around like random_step
+    pub fn record(val: M) -> Self
+    where
+        M: fmt::Debug,
+    {
+        let name = format!("record({:?})", val);
+        let val_back = val.clone();
+        Self::new(
+            move |macrostate, _| macrostate.wrapping_add(&val),
+            move |macrostate, _| macrostate.wrapping_sub(&val_back),
+        )
+        .with_name(name)
+    }
+
+    // Like record, but checked for overflow like random_step_checked
+    pub fn record_checked(val: M) -> Self
+    where
+        M: fmt::Debug,
+    {
+        let name = format!("record_checked({:?})", val);
+        let (val_back, val_try, val_try_back) = (val.clone(), val.clone(), val.clone());
+        Self::new(
+            move |macrostate, _| macrostate.clone() + val.clone(),
+            move |macrostate, _| macrostate.clone() - val_back.clone(),
+        )
+        .with_try_evolve(
+            move |macrostate, _| checked(macrostate.checked_add(&val_try)),
+            move |macrostate, _| checked(macrostate.checked_sub(&val_try_back)),
+        )
+        .with_name(name)
+    }
+}
+
+fn checked<M>(macrostate: Option<M>) -> Result<M, StepError> {
+    macrostate.ok_or(StepError::Overflow)
+}
+
+impl Transition {
+    // Walk on a ring of the given size, keeping the macrostate in 0..modulus
+    // The sum is taken in i64, so it can't overflow before the reduction for large moduli
+    pub fn modular_step(modulus: i32) -> Self {
+        assert!(modulus > 0, "modulus must be positive");
+        let reduce = move |sum: i64| sum.rem_euclid(i64::from(modulus)) as i32;
+        Self::new(
+            move |&macrostate, dice| reduce(i64::from(macrostate) + i64::from(dice)),
+            move |&macrostate, dice| reduce(i64::from(macrostate) - i64::from(dice)),
+        )
+        .with_name(format!("modular_step({})", modulus))
+    }
+
+    // XOR the die into the macrostate, which is its own inverse
+    pub fn xor_step() -> Self {
+        Self::new(
+            |&macrostate, dice| macrostate ^ dice,
+            |&macrostate, dice| macrostate ^ dice,
+        )
+        .with_name("xor_step")
+    }
+
+    // Rotate the macrostate's bits left by the given amount, ignoring the die
+    pub fn rotate(bits: u32) -> Self {
+        Self::new(
+            move |&macrostate, _| (macrostate as u32).rotate_left(bits) as i32,
+            move |&macrostate, _| (macrostate as u32).rotate_right(bits) as i32,
+        )
+        .with_name(format!("rotate({})", bits))
+    }
+
+    // Map m to a * m + b * dice + c, which is invertible over i32 only when a is 1 or -1
+    // Since a * a == 1, the inverse multiplies by a again rather than dividing. The arithmetic
+    // wraps around like random_step, which keeps it exact: 1 and -1 are units modulo 2^32
+    pub fn affine(a: i32, b: i32, c: i32) -> Self {
+        assert!(a == 1 || a == -1, "affine coefficient {} is not 1 or -1", a);
+        Self::new(
+            move |&macrostate: &i32, dice: i32| {
+                a.wrapping_mul(macrostate)
+                    .wrapping_add(b.wrapping_mul(dice))
+                    .wrapping_add(c)
+            },
+            move |&macrostate: &i32, dice: i32| {
+                a.wrapping_mul(
+                    macrostate
+                        .wrapping_sub(b.wrapping_mul(dice))
+                        .wrapping_sub(c),
+                )
+            },
+        )
+        .with_name(format!("affine({}, {}, {})", a, b, c))
+    }
+
+    // Walk as with random_step, but confined to lo..=hi: try_step_forward and try_step_backward
+    // refuse any step that would leave the range with StepError::OutOfBounds. Clamping at the
+    // walls would merge macrostates and lose reversibility, so the move is refused instead.
+    // The infallible step_* methods don't check the bounds
+    pub fn try_saturating_step(lo: i32, hi: i32) -> Self {
+        assert!(lo <= hi, "saturating range {}..={} is empty", lo, hi);
+        let bounded = move |macrostate: Option<i32>| match macrostate {
+            Some(macrostate) if (lo..=hi).contains(&macrostate) => Ok(macrostate),
+            Some(_) => Err(StepError::OutOfBounds),
+            None => Err(StepError::Overflow),
+        };
+        Self::new(
+            |&macrostate, dice| macrostate + dice,
+            |&macrostate, dice| macrostate - dice,
+        )
+        .with_try_evolve(
+            move |&macrostate, dice| bounded(macrostate.checked_add(dice)),
+            move |&macrostate, dice| bounded(macrostate.checked_sub(dice)),
+        )
+        .with_name(format!("try_saturating_step({}, {})", lo, hi))
+    }
+
+    // Add val * scale to the macrostate at every step, wrapping around like record
+    // The shift itself overflowing would record the wrong value, so debug builds check it
+    pub fn scaled_record(val: i32, scale: i32) -> Self {
+        debug_assert!(
+            val.checked_mul(scale).is_some(),
+            "scaled record {} * {} overflows",
+            val,
+            scale
+        );
+        let shift = val.wrapping_mul(scale);
+        Self::new(
+            move |&macrostate, _| macrostate.wrapping_add(shift),
+            move |&macrostate, _| macrostate.wrapping_sub(shift),
+        )
+        .with_name(format!("scaled_record({}, {})", val, scale))
+    }
+
+    // Swap the macrostate with the stashed value at every step, ignoring the die
+    // A swap is its own inverse, so stepping either way swaps back. The stash is atomic rather
+    // than a Cell because transitions are shared across threads
+    pub fn swap_stash(stash: Arc<AtomicI32>) -> Self {
+        let stash_back = stash.clone();
+        Self::new(
+            move |&macrostate, _| stash.swap(macrostate, Ordering::SeqCst),
+            move |&macrostate, _| stash_back.swap(macrostate, Ordering::SeqCst),
+        )
+        .with_name("swap_stash")
+    }
+
+    // Map a macrostate in 0..perm.len() through perm, ignoring the die
+    // Panics unless inverse undoes perm, so that both are permutations of the same size
+    pub fn permutation(perm: Vec<i32>, inverse: Vec<i32>) -> Self {
+        assert_eq!(perm.len(), inverse.len(), "permutation sizes differ");
+        let undoes = |f: &[i32], g: &[i32]| {
+            (0..f.len()).all(|i| {
+                let j = f[i];
+                0 <= j && (j as usize) < g.len() && g[j as usize] == i as i32
+            })
+        };
+        assert!(
+            undoes(&perm, &inverse) && undoes(&inverse, &perm),
+            "inverse does not undo the permutation"
+        );
+        let name = format!("permutation({:?})", perm);
+        Self::new(
+            move |&macrostate, _| perm[macrostate as usize],
+            move |&macrostate, _| inverse[macrostate as usize],
+        )
+        .with_name(name)
+    }
+}
+
+impl Transition<(i32, i32)> {
+    // Walk on a 2D lattice, adding dice [dx, dy] with faces 0..6 to the (x, y) macrostate
+    // Both dice are the base-6 digits of the state's one die, so the state must roll faces
+    // 0..36: build it with State::from_seed_2d or State::new_2d. A state with the default
+    // faces 0..6 always rolls dy = 0 and never moves in y
+    pub fn random_step_2d() -> Self {
+        Self::multi_dice::<2>(
+            6,
+            |&(x, y), dice| (x + dice[0], y + dice[1]),
+            |&(x, y), dice| (x - dice[0], y - dice[1]),
+        )
+        .with_name("random_step_2d")
+    }
+
+    // Second-order dynamics on a (position, velocity) macrostate: the die kicks the velocity,
+    // which then moves the position. Undoing the moves in reverse order makes it exact, and
+    // the arithmetic wraps around like random_step
+    pub fn leapfrog() -> Self {
+        Self::new(
+            |&(pos, vel), dice| {
+                let vel = vel.wrapping_add(dice);
+                (pos.wrapping_add(vel), vel)
+            },
+            |&(pos, vel), dice| (pos.wrapping_sub(vel), vel.wrapping_sub(dice)),
+        )
+        .with_name("leapfrog")
+    }
+
+    // Walk as with random_step on a (value, checksum) macrostate, folding each die into the
+    // checksum by rotating it and adding a scrambled die. The rotation makes the checksum
+    // depend on the order of the dice, and each fold is undone exactly when stepping back with
+    // the same die, so a rewind that comes back to the starting time step with a different
+    // checksum has replayed a tampered history
+    pub fn checksum_step() -> Self {
+        let scramble = |dice: i32| (dice as u32).wrapping_mul(0x9E37_79B1) as i32;
+        Self::new(
+            move |&(value, checksum), dice| {
+                (
+                    value.wrapping_add(dice),
+                    checksum.rotate_left(5).wrapping_add(scramble(dice)),
+                )
+            },
+            move |&(value, checksum), dice| {
+                (
+                    value.wrapping_sub(dice),
+                    checksum.wrapping_sub(scramble(dice)).rotate_right(5),
+                )
+            },
+        )
+        .with_name("checksum_step")
+    }
+
+    // A tiny reversible machine on a (program counter, register) macrostate: each step runs
+    // the op at the program counter on the register and moves on to the next op, looping back
+    // to the start after the last. Stepping back undoes the previous op. The program counter
+    // must start in 0..ops.len(); panics if the program is empty
+    pub fn program(ops: Vec<Op>) -> Self {
+        assert!(!ops.is_empty(), "program has no ops");
+        let name = format!("program({:?})", ops);
+        let ops = Arc::new(ops);
+        let ops_back = ops.clone();
+        let len = ops.len() as i32;
+        Self::new(
+            move |&(pc, register), dice| ((pc + 1) % len, ops[pc as usize].apply(register, dice)),
+            move |&(pc, register), dice| {
+                let pc = (pc - 1).rem_euclid(len);
+                (pc, ops_back[pc as usize].undo(register, dice))
+            },
+        )
+        .with_name(name)
+    }
+
+    // Walk confined to lo..=hi on a (position, direction) macrostate, with direction 1 or -1
+    // The die moves the walker along its direction of travel, bouncing off walls half a cell
+    // beyond lo and hi. Position alone would not be reversible, since two positions can
+    // reflect onto the same cell, so the direction is carried to keep each step a bijection
+    pub fn reflecting_step(lo: i32, hi: i32) -> Self {
+        assert!(lo <= hi, "reflecting range {}..={} is empty", lo, hi);
+        let name = format!("reflecting_step({}, {})", lo, hi);
+        let (lo, n) = (lo as i64, hi as i64 - lo as i64 + 1);
+        // Unfold onto a ring of size 2n, where the second half is the walk moving down
+        let unfold = move |&(position, direction): &(i32, i32)| {
+            let offset = position as i64 - lo;
+            if direction < 0 {
+                2 * n - 1 - offset
+            } else {
+                offset
+            }
+        };
+        let fold = move |u: i64| {
+            let u = u.rem_euclid(2 * n);
+            if u < n {
+                ((lo + u) as i32, 1)
+            } else {
+                ((lo + 2 * n - 1 - u) as i32, -1)
+            }
+        };
+        Self::new(
+            move |macrostate, dice| fold(unfold(macrostate) + dice as i64),
+            move |macrostate, dice| fold(unfold(macrostate) - dice as i64),
+        )
+        .with_name(name)
+    }
+}
+
+impl Transition<f64> {
+    // Walk continuously, adding the die times scale to the macrostate
+    // Floating-point addition rounds, so stepping back doesn't exactly undo a step and a long
+    // rewind drifts away from where the walk started; see kahan_step to reduce the drift
+    pub fn continuous_step(scale: f64) -> Self {
+        Self::new(
+            move |&macrostate, dice| macrostate + dice as f64 * scale,
+            move |&macrostate, dice| macrostate - dice as f64 * scale,
+        )
+        .approximate()
+        .with_name(format!("continuous_step({})", scale))
+    }
+
+    // Check the round trip identity to within tol for each of the given (macrostate, die) pairs
+    pub fn is_reversible_approx(&self, samples: &[(f64, i32)], tol: f64) -> bool {
+        samples.iter().all(|&(macrostate, die)| {
+            let next = (self.evolve_forward)(&macrostate, die);
+            ((self.evolve_backward)(&next, die) - macrostate).abs() <= tol
+        })
+    }
+}
+
+impl Transition<(f64, f64)> {
+    // Walk as with continuous_step on a (sum, compensation) macrostate, adding with Kahan
+    // summation: the compensation holds the rounding error of the sum so far, and is taken off
+    // the next step before it is added. The walk's position is sum - compensation. Stepping
+    // back adds the negated step the same way, which is still not exact but drifts far less
+    pub fn kahan_step(scale: f64) -> Self {
+        let add = |(sum, compensation): (f64, f64), value: f64| {
+            let adjusted = value - compensation;
+            let total = sum + adjusted;
+            (total, (total - sum) - adjusted)
+        };
+        Self::new(
+            move |&macrostate, dice| add(macrostate, dice as f64 * scale),
+            move |&macrostate, dice| add(macrostate, -(dice as f64) * scale),
+        )
+        .approximate()
+        .with_name(format!("kahan_step({})", scale))
+    }
+
+    // Check the round trip identity to within tol for each of the given (macrostate, die) pairs,
+    // comparing positions, sum - compensation, rather than the pairs themselves
+    pub fn is_reversible_approx(&self, samples: &[((f64, f64), i32)], tol: f64) -> bool {
+        let position = |(sum, compensation): (f64, f64)| sum - compensation;
+        samples.iter().all(|&(macrostate, die)| {
+            let next = (self.evolve_forward)(&macrostate, die);
+            (position((self.evolve_backward)(&next, die)) - position(macrostate)).abs() <= tol
+        })
+    }
+}
+
+impl<const K: usize> Transition<[i32; K]> {
+    // Add the die to a K-limb integer, little-endian with 32 bits per limb, carrying into the
+    // higher limbs. A negative die is sign-extended across the limbs, and the top limb wraps
+    // around like random_step, so stepping back subtracts with borrows and is exact
+    pub fn carry_add() -> Self {
+        Self::new(
+            |&limbs, dice| add_limbs(limbs, dice, false),
+            |&limbs, dice| add_limbs(limbs, dice, true),
+        )
+        .with_name(format!("carry_add::<{}>", K))
+    }
+}
+
+// Add or subtract the sign-extended die to the limbs of a little-endian two's complement integer
+fn add_limbs<const K: usize>(mut limbs: [i32; K], die: i32, subtract: bool) -> [i32; K] {
+    let extension = if die < 0 { u32::MAX } else { 0 };
+    let mut carry = false;
+    for (k, limb) in limbs.iter_mut().enumerate() {
+        let digit = if k == 0 { die as u32 } else { extension };
+        let (value, over) = if subtract {
+            let (value, a) = (*limb as u32).overflowing_sub(digit);
+            let (value, b) = value.overflowing_sub(u32::from(carry));
+            (value, a || b)
+        } else {
+            let (value, a) = (*limb as u32).overflowing_add(digit);
+            let (value, b) = value.overflowing_add(u32::from(carry));
+            (value, a || b)
+        };
+        *limb = value as i32;
+        carry = over;
+    }
+    limbs
+}
+
+impl<const N: usize> Transition<([i32; N], u64)> {
+    // A reversible comparator on an (array, carry) macrostate: when the die is odd, put
+    // entries i and j in order so that array[i] <= array[j], otherwise leave them. Sorting
+    // alone would forget the order it started from, so each step shifts whether it swapped into
+    // the carry, and stepping back pops that bit to undo the swap. Compose several, with a die
+    // that is always odd, for a reversible sorting network. The 64 bits of a zero carry are
+    // shared by every comparator application, not every step: a network of c comparators runs
+    // for 64 / c steps, only 21 for three. Once the carry is full, step_forward panics and
+    // try_step_forward fails with StepError::Overflow
+    pub fn compare_exchange(i: usize, j: usize) -> Self {
+        assert!(
+            i < N && j < N,
+            "comparator {}, {} is out of bounds for {}",
+            i,
+            j,
+            N
+        );
+        let exchange = move |mut array: [i32; N], carry: u64, dice: i32| {
+            let swap = dice & 1 == 1 && array[i] > array[j];
+            if swap {
+                array.swap(i, j);
+            }
+            (array, carry << 1 | u64::from(swap))
+        };
+        let unexchange = move |&(mut array, carry): &([i32; N], u64), _| {
+            if carry & 1 == 1 {
+                array.swap(i, j);
+            }
+            (array, carry >> 1)
+        };
+        Self::new(
+            move |&(array, carry), dice| {
+                assert!(carry >> 63 == 0, "compare_exchange carry is full");
+                exchange(array, carry, dice)
+            },
+            unexchange,
+        )
+        .with_try_evolve(
+            move |&(array, carry), dice| match carry >> 63 {
+                0 => Ok(exchange(array, carry, dice)),
+                _ => Err(StepError::Overflow),
+            },
+            move |macrostate, dice| Ok(unexchange(macrostate, dice)),
+        )
+        .with_name(format!("compare_exchange({}, {})", i, j))
+    }
+}
+
+impl<M: 'static> Transition<M> {
+    // Apply self and then other as a single step; backward undoes other before self
+    pub fn compose(self, other: Transition<M>) -> Self {
+        let name = format!("{:?}.compose({:?})", self, other);
+        let exact = self.exact && other.exact;
+        let pair = Arc::new((self, other));
+        let (forward, backward, try_forward) = (pair.clone(), pair.clone(), pair.clone());
+        let mut composed = Self::new(
+            move |macrostate, die| {
+                let (first, second) = &*forward;
+                (second.evolve_forward)(&(first.evolve_forward)(macrostate, die), die)
+            },
+            move |macrostate, die| {
+                let (first, second) = &*backward;
+                (first.evolve_backward)(&(second.evolve_backward)(macrostate, die), die)
+            },
+        )
+        .with_try_evolve(
+            move |macrostate, die| {
+                let (first, second) = &*try_forward;
+                second.try_forward(&first.try_forward(macrostate, die)?, die)
+            },
+            move |macrostate, die| {
+                let (first, second) = &*pair;
+                first.try_backward(&second.try_backward(macrostate, die)?, die)
+            },
+        )
+        .with_name(name);
+        composed.exact = exact;
+        composed
+    }
+
+    // Apply even when the die is even and odd when it is odd, passing the die on to either
+    // Stepping back sees the same die, so it undoes the step with whichever branch took it,
+    // and the result is reversible whenever both branches are
+    pub fn branch_on_die(even: Transition<M>, odd: Transition<M>) -> Self {
+        let name = format!("branch_on_die({:?}, {:?})", even, odd);
+        let exact = even.exact && odd.exact;
+        let branches = Arc::new((even, odd));
+        let (forward, backward, try_forward) =
+            (branches.clone(), branches.clone(), branches.clone());
+        let mut branched = Self::new(
+            move |macrostate, die| (pick_branch(&forward, die).evolve_forward)(macrostate, die),
+            move |macrostate, die| (pick_branch(&backward, die).evolve_backward)(macrostate, die),
+        )
+        .with_try_evolve(
+            move |macrostate, die| pick_branch(&try_forward, die).try_forward(macrostate, die),
+            move |macrostate, die| pick_branch(&branches, die).try_backward(macrostate, die),
+        )
+        .with_name(name);
+        branched.exact = exact;
+        branched
+    }
+
+    // Pass each step N dice with faces 0..faces instead of one, for evolutions that need several
+    // random inputs. They are the base-faces digits of the single die rolled per step, least
+    // significant first, so the state's die should have faces 0..faces^N for them to be uniform
+    // and independent. Reversibility then only has to hold for each fixed slice of dice
+    pub fn multi_dice<const N: usize>(
+        faces: i32,
+        forward: impl Fn(&M, &[i32]) -> M + Send + Sync + 'static,
+        backward: impl Fn(&M, &[i32]) -> M + Send + Sync + 'static,
+    ) -> Self {
+        assert!(faces > 0, "dice must have at least one face");
+        Self::new(
+            move |macrostate, die| forward(macrostate, &split_die::<N>(die, faces)),
+            move |macrostate, die| backward(macrostate, &split_die::<N>(die, faces)),
+        )
+    }
+}
+
+// The branch of branch_on_die that takes the given die
+fn pick_branch<M>(branches: &(Transition<M>, Transition<M>), die: i32) -> &Transition<M> {
+    if die.rem_euclid(2) == 0 {
+        &branches.0
+    } else {
+        &branches.1
+    }
+}
+
+// The N base-faces digits of die, least significant first
+fn split_die<const N: usize>(mut die: i32, faces: i32) -> [i32; N] {
+    let mut dice = [0; N];
+    for digit in &mut dice {
+        *digit = die.rem_euclid(faces);
+        die = die.div_euclid(faces);
+    }
+    dice
+}
+
+impl<M: PartialEq> Transition<M> {
+    // Check the round trip identity for each of the given (macrostate, die) pairs
+    pub fn is_reversible(&self, samples: &[(M, i32)]) -> bool {
+        samples
+            .iter()
+            .all(|(macrostate, die)| self.round_trips(macrostate, *die))
+    }
+
+    // Panic in debug builds if the round trip identity fails for this pair, unless the
+    // transition is only approximately reversible
+    pub fn debug_assert_reversible(&self, macrostate: &M, die: i32) {
+        debug_assert!(
+            !self.exact || self.round_trips(macrostate, die),
+            "evolve_backward does not undo evolve_forward"
+        );
+    }
+
+    fn round_trips(&self, macrostate: &M, die: i32) -> bool {
+        let next = (self.evolve_forward)(macrostate, die);
+        (self.evolve_backward)(&next, die) == *macrostate
+    }
+}
+
+// Parse idle, random, record:<val>, modular:<modulus>, or affine:<a>,<b>,<c>
+impl TryFrom<&str> for Transition {
+    type Error = ParseTransitionError;
+
+    fn try_from(spec: &str) -> Result<Self, Self::Error> {
+        let error = |reason: String| ParseTransitionError {
+            spec: spec.to_string(),
+            reason,
+        };
+        let int = |arg: &str| {
+            arg.trim()
+                .parse::<i32>()
+                .map_err(|e| error(format!("{:?} is not an integer: {}", arg, e)))
+        };
+        let (name, args) = match spec.split_once(':') {
+            Some((name, args)) => (name, Some(args)),
+            None => (spec, None),
+        };
+        match (name, args) {
+            ("idle", None) => Ok(Transition::idle()),
+            ("random", None) => Ok(Transition::random_step()),
+            ("record", Some(val)) => Ok(Transition::record(int(val)?)),
+            ("modular", Some(modulus)) => match int(modulus)? {
+                modulus if modulus > 0 => Ok(Transition::modular_step(modulus)),
+                _ => Err(error("modulus must be positive".to_string())),
+            },
+            ("affine", Some(coefficients)) => {
+                let coefficients = coefficients
+                    .split(',')
+                    .map(int)
+                    .collect::<Result<Vec<_>, _>>()?;
+                match coefficients[..] {
+                    [a, b, c] if a == 1 || a == -1 => Ok(Transition::affine(a, b, c)),
+                    [_, _, _] => Err(error("affine coefficient a must be 1 or -1".to_string())),
+                    _ => Err(error("affine takes three coefficients a,b,c".to_string())),
+                }
+            }
+            ("idle" | "random", Some(_)) => Err(error(format!("{} takes no arguments", name))),
+            ("record" | "modular" | "affine", None) => {
+                Err(error(format!("{} needs arguments after a colon", name)))
+            }
+            _ => Err(error(
+                "expected idle, random, record:<val>, modular:<modulus>, or affine:<a>,<b>,<c>"
+                    .to_string(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::State;
+
+    #[test]
+    fn xor_step_round_trips() {
+        let transition = Transition::xor_step();
+        for m in (-1000..1000).chain([i32::MIN, i32::MAX]) {
+            for die in -8..8 {
+                let next = (transition.evolve_forward)(&m, die);
+                assert_eq!(
+                    (transition.evolve_backward)(&next, die),
+                    m,
+                    "m={m} die={die}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reflecting_step_round_trips_within_bounds() {
+        let (lo, hi) = (-3, 4);
+        let transition = Transition::reflecting_step(lo, hi);
+        for position in lo..=hi {
+            for direction in [1, -1] {
+                for die in -20..20 {
+                    let m = (position, direction);
+                    let next = (transition.evolve_forward)(&m, die);
+                    assert!(
+                        (lo..=hi).contains(&next.0),
+                        "{m:?} left bounds with die {die}"
+                    );
+                    assert!(
+                        next.1 == 1 || next.1 == -1,
+                        "{m:?} has direction {}",
+                        next.1
+                    );
+                    assert_eq!((transition.evolve_backward)(&next, die), m, "die={die}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn affine_negation_round_trips_at_the_extremes() {
+        for (b, c) in [(1, 0), (3, -7), (-2, i32::MAX)] {
+            let transition = Transition::affine(-1, b, c);
+            let extremes = [i32::MIN, i32::MIN + 1, -1, 0, 1, i32::MAX - 1, i32::MAX];
+            for m in extremes {
+                for die in 0..6 {
+                    let next = (transition.evolve_forward)(&m, die);
+                    assert_eq!(
+                        (transition.evolve_backward)(&next, die),
+                        m,
+                        "m={m} die={die}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn affine_steps_from_i32_min() {
+        let transition = Transition::affine(-1, 1, 0);
+        let mut state = State::from_seed(i32::MIN, 3);
+        state.step_forward_n(&transition, 10);
+        state.step_backward_n(&transition, 10);
+        assert_eq!(*state.macrostate(), i32::MIN);
+    }
+
+    #[test]
+    fn swap_stash_twice_is_the_identity() {
+        let stash = Arc::new(AtomicI32::new(7));
+        let transition = Transition::swap_stash(stash.clone());
+        let mut state = State::from_seed(3, 1);
+        state.step_forward(&transition);
+        assert_eq!((*state.macrostate(), stash.load(Ordering::SeqCst)), (7, 3));
+        state.step_forward(&transition);
+        assert_eq!((*state.macrostate(), stash.load(Ordering::SeqCst)), (3, 7));
+        state.step_backward(&transition);
+        assert_eq!((*state.macrostate(), stash.load(Ordering::SeqCst)), (7, 3));
+    }
+
+    #[test]
+    fn scaled_record_round_trips_near_i32_max() {
+        let (start, shift) = (i32::MAX - 1_000, 46_340 * 46_340);
+        let transition = Transition::scaled_record(46_340, 46_340);
+        let mut state = State::from_seed(start, 1);
+        state.step_forward_n(&transition, 3);
+        let expected = start
+            .wrapping_add(shift)
+            .wrapping_add(shift)
+            .wrapping_add(shift);
+        assert_eq!(*state.macrostate(), expected);
+        state.step_backward_n(&transition, 3);
+        assert_eq!(*state.macrostate(), start);
+    }
+
+    #[test]
+    #[should_panic(expected = "overflows")]
+    #[cfg(debug_assertions)]
+    fn scaled_record_rejects_an_overflowing_shift() {
+        Transition::scaled_record(i32::MAX, 2);
+    }
+
+    #[test]
+    fn leapfrog_round_trips() {
+        let transition = Transition::leapfrog();
+        let start = (i32::MAX - 5, i32::MAX - 2);
+        let mut state = State::from_seed(start, 3);
+        state.step_forward_n(&transition, 50);
+        assert_ne!(*state.macrostate(), start);
+        state.step_backward_n(&transition, 50);
+        assert_eq!(*state.macrostate(), start);
+    }
+
+    #[test]
+    fn rotate_round_trips() {
+        for bits in [0, 1, 5, 31, 32, 33] {
+            let transition = Transition::rotate(bits);
+            for macrostate in [0, 1, -1, 0x1234_5678, i32::MIN, i32::MAX] {
+                let mut state = State::from_seed(macrostate, 1);
+                state.step_forward(&transition);
+                state.step_backward(&transition);
+                assert_eq!(*state.macrostate(), macrostate, "rotate({})", bits);
+            }
+        }
+        let mut state = State::from_seed(i32::MIN, 1);
+        state.step_forward(&Transition::rotate(1));
+        assert_eq!(*state.macrostate(), 1);
+    }
+
+    #[test]
+    fn checksum_step_detects_a_tampered_history() {
+        let transition = Transition::checksum_step();
+        let rewind_with = |tamper: fn(&mut Vec<i32>)| {
+            let mut state = State::from_dice((0, 0), vec![1, 4, 2, 5, 0, 3]);
+            state.step_forward_n(&transition, 6);
+            let mut dice = state.drain_past();
+            tamper(&mut dice);
+            state.prepend_past_dice(&dice);
+            state.step_backward_n(&transition, 6);
+            *state.macrostate()
+        };
+        assert_eq!(rewind_with(|_| {}), (0, 0));
+        let corrupted = rewind_with(|dice| dice[2] += 1);
+        assert_ne!(corrupted.1, 0);
+        let reordered = rewind_with(|dice| dice.swap(0, 5));
+        assert_eq!(reordered.0, 0);
+        assert_ne!(reordered.1, 0);
+    }
+
+    fn sorting_network() -> Transition<([i32; 3], u64)> {
+        Transition::compare_exchange(0, 1)
+            .compose(Transition::compare_exchange(1, 2))
+            .compose(Transition::compare_exchange(0, 1))
+    }
+
+    #[test]
+    fn sorting_network_sorts_and_rewinds() {
+        let network = sorting_network();
+        for array in [[3, 2, 1], [2, 3, 1], [1, 3, 2], [1, 2, 3], [5, -5, 5]] {
+            let mut state = State::from_dice((array, 0), vec![1; 21]);
+            state.step_forward(&network);
+            let mut sorted = array;
+            sorted.sort();
+            assert_eq!(state.macrostate().0, sorted);
+            state.step_forward_n(&network, 20);
+            state.step_backward_n(&network, 21);
+            assert_eq!(*state.macrostate(), (array, 0));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "carry is full")]
+    fn sorting_network_runs_out_of_carry() {
+        let mut state = State::from_dice(([3, 2, 1], 0), vec![1; 22]);
+        state.step_forward_n(&sorting_network(), 22);
+    }
+
+    #[test]
+    fn carry_add_rewinds_to_zero() {
+        let transition = Transition::<[i32; 2]>::carry_add();
+        let mut dice = vec![-1];
+        dice.extend([i32::MAX; 8]);
+        dice.extend([-1, i32::MIN, 7, -7]);
+        let steps = dice.len();
+        let mut state = State::from_dice([0; 2], dice);
+        state.step_forward(&transition);
+        assert_eq!(*state.macrostate(), [-1, -1]);
+        state.step_forward_n(&transition, steps - 1);
+        assert_eq!(*state.macrostate(), [i32::MAX - 9, 3]);
+        state.step_backward_n(&transition, steps);
+        assert_eq!(*state.macrostate(), [0; 2]);
+    }
+
+    #[test]
+    fn carry_add_carries_into_and_wraps_the_top_limb() {
+        let transition = Transition::<[i32; 2]>::carry_add();
+        let mut state = State::from_dice([-1, i32::MAX], vec![1, 1, -1]);
+        state.step_forward(&transition);
+        assert_eq!(*state.macrostate(), [0, i32::MIN]);
+        state.step_forward(&transition);
+        assert_eq!(*state.macrostate(), [1, i32::MIN]);
+        state.step_forward(&transition);
+        assert_eq!(*state.macrostate(), [0, i32::MIN]);
+
+        let mut state = State::from_dice([-1, -1], vec![1]);
+        state.step_forward(&transition);
+        assert_eq!(*state.macrostate(), [0, 0]);
+        state.step_backward(&transition);
+        assert_eq!(*state.macrostate(), [-1, -1]);
+    }
+
+    #[test]
+    fn modular_step_stays_on_the_ring_and_round_trips() {
+        let transition = Transition::modular_step(5);
+        let mut state = State::from_seed(0, 4);
+        for _ in 0..100 {
+            state.step_forward(&transition);
+            assert!((0..5).contains(state.macrostate()));
+        }
+        state.step_backward_n(&transition, 100);
+        assert_eq!(*state.macrostate(), 0);
+    }
+
+    #[test]
+    fn modular_step_handles_the_largest_modulus() {
+        let transition = Transition::try_from("modular:2147483647").unwrap();
+        let mut state = State::from_dice(i32::MAX - 1, vec![5]);
+        state.step_forward(&transition);
+        assert_eq!(*state.macrostate(), 4);
+        state.step_backward(&transition);
+        assert_eq!(*state.macrostate(), i32::MAX - 1);
+    }
+
+    #[test]
+    fn try_stepping_a_full_carry_overflows() {
+        let mut state = State::from_dice(([3, 2, 1], 0), vec![1; 22]);
+        let network = sorting_network();
+        state.step_forward_n(&network, 21);
+        assert_eq!(state.try_step_forward(&network), Err(StepError::Overflow));
+        assert_eq!(state.t(), 21);
+        state.step_backward_n(&network, 21);
+        assert_eq!(*state.macrostate(), ([3, 2, 1], 0));
+    }
+
+    #[test]
+    fn branch_on_die_round_trips_across_both_parities() {
+        let transition =
+            Transition::branch_on_die(Transition::random_step(), Transition::xor_step());
+        let dice = vec![2, 3, 4, 5, -1, 0, 7, -6];
+        let mut state = State::from_dice(1, dice.clone());
+        state.step_forward(&transition);
+        assert_eq!(*state.macrostate(), 3);
+        state.step_forward(&transition);
+        assert_eq!(*state.macrostate(), 0);
+        state.step_forward_n(&transition, dice.len() - 2);
+        state.step_backward_n(&transition, dice.len());
+        assert_eq!(*state.macrostate(), 1);
+        assert_eq!(
+            state
+                .future_dice()
+                .iter()
+                .rev()
+                .copied()
+                .collect::<Vec<_>>(),
+            dice
+        );
+
+        assert!(transition.exact);
+        let odd_approximate = Transition::branch_on_die(
+            Transition::random_step(),
+            Transition::xor_step().approximate(),
+        );
+        assert!(!odd_approximate.exact);
+        let even_approximate = Transition::branch_on_die(
+            Transition::random_step().approximate(),
+            Transition::xor_step(),
+        );
+        assert!(!even_approximate.exact);
+    }
+}
@@ -0,0 +1,37 @@
+// A reversible instruction acting on the register of Transition::program
+// Arithmetic wraps around, so every op is a bijection on i32 and undo inverts apply exactly
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Add(i32),
+    // Add the die rolled for this step
+    AddDie,
+    Xor(i32),
+    // XOR in the die rolled for this step
+    XorDie,
+    Negate,
+    RotateLeft(u32),
+}
+
+impl Op {
+    pub fn apply(&self, register: i32, die: i32) -> i32 {
+        match *self {
+            Op::Add(n) => register.wrapping_add(n),
+            Op::AddDie => register.wrapping_add(die),
+            Op::Xor(n) => register ^ n,
+            Op::XorDie => register ^ die,
+            Op::Negate => register.wrapping_neg(),
+            Op::RotateLeft(bits) => register.rotate_left(bits),
+        }
+    }
+
+    pub fn undo(&self, register: i32, die: i32) -> i32 {
+        match *self {
+            Op::Add(n) => register.wrapping_sub(n),
+            Op::AddDie => register.wrapping_sub(die),
+            Op::Xor(n) => register ^ n,
+            Op::XorDie => register ^ die,
+            Op::Negate => register.wrapping_neg(),
+            Op::RotateLeft(bits) => register.rotate_right(bits),
+        }
+    }
+}
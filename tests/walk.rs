@@ -0,0 +1,36 @@
+use multibaker::{State, Transition};
+
+#[test]
+fn walk_forward_and_back_through_the_public_api() {
+    let transition = Transition::random_step();
+    let mut state = State::from_seed(0, 42);
+    for _ in 0..50 {
+        state.step_forward(&transition);
+    }
+    assert_eq!(state.t(), 50);
+    assert_eq!(
+        *state.macrostate(),
+        state.past_dice().iter().sum::<i32>(),
+        "random_step should add each die"
+    );
+    for _ in 0..50 {
+        state.step_backward(&transition);
+    }
+    assert_eq!(state.t(), 0);
+    assert_eq!(*state.macrostate(), 0);
+    assert!(state.past_dice().is_empty());
+    assert_eq!(state.future_dice().len(), 50);
+}
+
+#[test]
+fn helper_constructors_are_public() {
+    let dice = State::uniform_rolls(7);
+    let mut state = State::with_die_source(3, dice);
+    state.step_forward(&Transition::idle());
+    state.step_forward(&Transition::record(5));
+    assert_eq!(*state.macrostate(), 8, "record should add its value");
+    let perturbed = State::peturbed(&state);
+    assert_eq!(perturbed.t(), state.t());
+    assert_eq!(perturbed.macrostate(), state.macrostate());
+    let _ = State::new(0);
+}